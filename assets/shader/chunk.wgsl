@@ -0,0 +1,47 @@
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+#import bevy_pbr::mesh_functions
+
+@group(1) @binding(0)
+var atlas_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var atlas_sampler: sampler;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    // counts blocks, the fractional part is the position inside the tile
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    // min.xy, max.xy of the atlas tile
+    @location(3) tile: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) tile: vec4<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = mesh_position_local_to_clip(mesh.model, vec4<f32>(vertex.position, 1.0));
+    out.uv = vertex.uv;
+    out.color = vertex.color;
+    out.tile = vertex.tile;
+    return out;
+}
+
+struct FragmentInput {
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) tile: vec4<f32>,
+};
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    // repeat the tile once per block, merged quads span several
+    let uv = mix(in.tile.xy, in.tile.zw, fract(in.uv));
+    return textureSample(atlas_texture, atlas_sampler, uv) * in.color;
+}
@@ -0,0 +1,387 @@
+use std::path::Path;
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::{CompressedImageFormats, ImageSampler, ImageType},
+    },
+    utils::HashMap,
+};
+
+use super::{BlockRegistry, BlockTextures, BlockType};
+use crate::chunk::mesh::Face;
+
+/// Tile size used when no block has a texture.
+const DEFAULT_TILE_SIZE: u32 = 16;
+/// Bytes per pixel of the atlas, which is always RGBA8.
+const PIXEL_SIZE: usize = 4;
+
+/// Arrangement of equally sized square tiles in a grid, filled row by row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasLayout {
+    pub tile_size: u32,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl AtlasLayout {
+    /// Smallest roughly square grid fitting `tile_count` tiles.
+    pub fn new(tile_count: usize, tile_size: u32) -> Self {
+        let tile_count = tile_count.max(1) as u32;
+        let columns = (tile_count as f32).sqrt().ceil() as u32;
+        let rows = tile_count.div_ceil(columns);
+
+        return Self {
+            tile_size,
+            columns,
+            rows,
+        };
+    }
+
+    /// Size of the whole atlas in pixels.
+    pub fn size(&self) -> UVec2 {
+        UVec2::new(self.columns, self.rows) * self.tile_size
+    }
+
+    /// Top left pixel of a tile.
+    pub fn tile_offset(&self, tile: u32) -> UVec2 {
+        UVec2::new(tile % self.columns, tile / self.columns) * self.tile_size
+    }
+
+    /// Area of a tile in texture coordinates.
+    pub fn tile_uv(&self, tile: u32) -> Rect {
+        let size = self.size().as_vec2();
+        let min = self.tile_offset(tile).as_vec2();
+
+        return Rect {
+            min: min / size,
+            max: (min + Vec2::splat(self.tile_size as f32)) / size,
+        };
+    }
+
+    /// Copies RGBA8 tiles into a single RGBA8 atlas, in tile order.
+    pub fn pack(&self, tiles: &[Vec<u8>]) -> Vec<u8> {
+        let size = self.size();
+        let tile_size = self.tile_size as usize;
+        let row_length = tile_size * PIXEL_SIZE;
+        let mut data = vec![0; size.x as usize * size.y as usize * PIXEL_SIZE];
+
+        for (tile, pixels) in tiles.iter().enumerate() {
+            assert_eq!(
+                pixels.len(),
+                tile_size * row_length,
+                "Atlas tile {} has the wrong size",
+                tile
+            );
+
+            let offset = self.tile_offset(tile as u32);
+
+            for row in 0..tile_size {
+                let target =
+                    ((offset.y as usize + row) * size.x as usize + offset.x as usize) * PIXEL_SIZE;
+
+                data[target..target + row_length]
+                    .copy_from_slice(&pixels[row * row_length..(row + 1) * row_length]);
+            }
+        }
+
+        return data;
+    }
+}
+
+/// Atlas tiles used by the faces of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaceTiles {
+    pub top: u32,
+    pub side: u32,
+    pub bottom: u32,
+}
+
+impl FaceTiles {
+    /// All faces on the white tile.
+    pub const UNTEXTURED: FaceTiles = FaceTiles {
+        top: 0,
+        side: 0,
+        bottom: 0,
+    };
+}
+
+/// All block textures packed into one image, so a chunk can be drawn with a single material.
+/// Tile 0 is plain white and used by blocks without textures, which are tinted by their colour instead.
+#[derive(Resource, Debug, Clone)]
+pub struct BlockAtlas {
+    pub layout: AtlasLayout,
+    /// Indexed by [`BlockType`].
+    faces: Vec<FaceTiles>,
+    pub image: Handle<Image>,
+}
+
+impl BlockAtlas {
+    /// Packs the textures of every registered block, loading each distinct file once.
+    /// `load_tile` receives texture paths as written in the block definitions
+    /// and returns the RGBA8 pixels of a square tile along with its side length.
+    pub fn build(
+        block_registry: &BlockRegistry,
+        mut load_tile: impl FnMut(&str) -> (Vec<u8>, u32),
+    ) -> (Self, Vec<u8>) {
+        let mut tiles: Vec<Vec<u8>> = Vec::new();
+        let mut tile_size = None;
+        let mut indices: HashMap<String, u32> = HashMap::new();
+
+        let mut tile_index = |path: &str, tiles: &mut Vec<Vec<u8>>| -> u32 {
+            if let Some(index) = indices.get(path) {
+                return *index;
+            }
+
+            let (pixels, size) = load_tile(path);
+            let expected_size = *tile_size.get_or_insert(size);
+            if size != expected_size {
+                panic!(
+                    "Block texture {:?} is {}x{}, expected {}x{} like the others",
+                    path, size, size, expected_size, expected_size
+                );
+            }
+
+            // tile 0 is reserved for the white tile, which is inserted last
+            tiles.push(pixels);
+            let index = tiles.len() as u32;
+            indices.insert(path.to_string(), index);
+
+            return index;
+        };
+
+        let faces = block_registry
+            .iter()
+            .map(|(_, block)| match &block.textures {
+                None => FaceTiles::UNTEXTURED,
+                Some(BlockTextures::All(path)) => {
+                    let tile = tile_index(path, &mut tiles);
+
+                    FaceTiles {
+                        top: tile,
+                        side: tile,
+                        bottom: tile,
+                    }
+                }
+                Some(BlockTextures::Faces { top, side, bottom }) => FaceTiles {
+                    top: tile_index(top, &mut tiles),
+                    side: tile_index(side, &mut tiles),
+                    bottom: tile_index(bottom, &mut tiles),
+                },
+            })
+            .collect();
+
+        let tile_size = tile_size.unwrap_or(DEFAULT_TILE_SIZE);
+        tiles.insert(0, vec![255; (tile_size * tile_size) as usize * PIXEL_SIZE]);
+
+        let layout = AtlasLayout::new(tiles.len(), tile_size);
+        let data = layout.pack(&tiles);
+
+        return (
+            Self {
+                layout,
+                faces,
+                image: Handle::default(),
+            },
+            data,
+        );
+    }
+
+    pub fn tile(&self, block_type: BlockType, face: Face) -> u32 {
+        let tiles = &self.faces[block_type.0 as usize];
+
+        match face {
+            Face::PosY => tiles.top,
+            Face::NegY => tiles.bottom,
+            _ => tiles.side,
+        }
+    }
+
+    pub fn tile_uv(&self, block_type: BlockType, face: Face) -> Rect {
+        self.layout.tile_uv(self.tile(block_type, face))
+    }
+
+    /// Whether the block uses the white tile and is only coloured by tinting.
+    pub fn is_untextured(&self, block_type: BlockType) -> bool {
+        self.faces[block_type.0 as usize] == FaceTiles::UNTEXTURED
+    }
+
+    /// Wraps packed atlas pixels into a texture, sampled without filtering to keep texels crisp.
+    pub fn create_image(&self, data: Vec<u8>) -> Image {
+        let size = self.layout.size();
+
+        let mut image = Image::new(
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        image.sampler_descriptor = ImageSampler::nearest();
+
+        return image;
+    }
+}
+
+/// Reads a PNG block texture from disk as RGBA8 pixels, along with its width.
+pub fn load_tile(path: &Path) -> (Vec<u8>, u32) {
+    let bytes = std::fs::read(path)
+        .unwrap_or_else(|error| panic!("Could not read block texture {:?}: {}", path, error));
+
+    let image = Image::from_buffer(
+        &bytes,
+        ImageType::Extension("png"),
+        CompressedImageFormats::NONE,
+        true,
+    )
+    .unwrap_or_else(|error| panic!("Invalid block texture {:?}: {}", path, error));
+
+    let image = match image.texture_descriptor.format {
+        TextureFormat::Rgba8UnormSrgb => image,
+        _ => image
+            .convert(TextureFormat::Rgba8UnormSrgb)
+            .unwrap_or_else(|| panic!("Unsupported pixel format in block texture {:?}", path)),
+    };
+
+    let size = image.texture_descriptor.size;
+    if size.width != size.height {
+        panic!("Block texture {:?} is not square", path);
+    }
+
+    return (image.data, size.width);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tile of a single colour, which is its first channel.
+    fn tile(value: u8, size: u32) -> Vec<u8> {
+        vec![value; (size * size) as usize * PIXEL_SIZE]
+    }
+
+    #[test]
+    fn layout_fits_all_tiles() {
+        for (tile_count, columns, rows) in [(0, 1, 1), (1, 1, 1), (2, 2, 1), (5, 3, 2), (9, 3, 3)] {
+            let layout = AtlasLayout::new(tile_count, 16);
+
+            assert_eq!(
+                (layout.columns, layout.rows),
+                (columns, rows),
+                "{}",
+                tile_count
+            );
+        }
+
+        let layout = AtlasLayout::new(5, 16);
+        assert_eq!(layout.size(), UVec2::new(48, 32));
+        assert_eq!(layout.tile_offset(0), UVec2::ZERO);
+        assert_eq!(layout.tile_offset(2), UVec2::new(32, 0));
+        assert_eq!(layout.tile_offset(4), UVec2::new(16, 16));
+    }
+
+    #[test]
+    fn tile_uvs() {
+        let layout = AtlasLayout::new(4, 8);
+
+        assert_eq!(
+            layout.tile_uv(0),
+            Rect {
+                min: Vec2::ZERO,
+                max: Vec2::splat(0.5)
+            }
+        );
+        assert_eq!(
+            layout.tile_uv(3),
+            Rect {
+                min: Vec2::splat(0.5),
+                max: Vec2::ONE
+            }
+        );
+    }
+
+    #[test]
+    fn pack_places_tiles_row_by_row() {
+        let layout = AtlasLayout::new(3, 2);
+        let data = layout.pack(&[tile(1, 2), tile(2, 2), tile(3, 2)]);
+        let size = layout.size();
+        let pixel = |x: u32, y: u32| data[((y * size.x + x) as usize) * PIXEL_SIZE];
+
+        assert_eq!(data.len(), (size.x * size.y) as usize * PIXEL_SIZE);
+        assert_eq!([pixel(0, 0), pixel(1, 1)], [1, 1]);
+        assert_eq!([pixel(2, 0), pixel(3, 1)], [2, 2]);
+        assert_eq!([pixel(0, 2), pixel(1, 3)], [3, 3]);
+        // the unused last tile stays empty
+        assert_eq!(pixel(2, 2), 0);
+    }
+
+    #[test]
+    fn build_loads_each_texture_once() {
+        let block_registry = BlockRegistry::from_assets();
+        let mut loaded = Vec::new();
+
+        let (block_atlas, data) = BlockAtlas::build(&block_registry, |path| {
+            loaded.push(path.to_string());
+            (tile(loaded.len() as u8, 4), 4)
+        });
+
+        // soil is the bottom of grass as well
+        let mut distinct = loaded.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), loaded.len());
+        assert_eq!(
+            loaded.iter().filter(|path| path.contains("soil")).count(),
+            1
+        );
+
+        let layout = block_atlas.layout;
+        assert!(layout.columns * layout.rows > loaded.len() as u32);
+        assert_eq!(
+            data.len(),
+            (layout.size().x * layout.size().y) as usize * PIXEL_SIZE
+        );
+        // the white tile comes first
+        assert_eq!(&data[..PIXEL_SIZE], &[255; PIXEL_SIZE]);
+
+        let grass = block_registry.by_id("grass").unwrap();
+        let soil = block_registry.by_id("soil").unwrap();
+        assert_ne!(
+            block_atlas.tile(grass, Face::PosY),
+            block_atlas.tile(grass, Face::PosX)
+        );
+        assert_eq!(
+            block_atlas.tile(grass, Face::PosX),
+            block_atlas.tile(grass, Face::NegZ)
+        );
+        assert_eq!(
+            block_atlas.tile(grass, Face::NegY),
+            block_atlas.tile(soil, Face::PosY)
+        );
+        assert_eq!(
+            block_atlas.tile_uv(soil, Face::PosX),
+            layout.tile_uv(block_atlas.tile(soil, Face::PosX))
+        );
+
+        let sand = block_registry.by_id("sand").unwrap();
+        assert!(block_atlas.is_untextured(sand));
+        assert!(!block_atlas.is_untextured(grass));
+        assert_eq!(block_atlas.tile(sand, Face::PosY), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected 4x4")]
+    fn build_rejects_mismatched_tile_sizes() {
+        let block_registry = BlockRegistry::from_assets();
+        let mut size = 2;
+
+        BlockAtlas::build(&block_registry, |_| {
+            size += 2;
+            (tile(0, size), size)
+        });
+    }
+}
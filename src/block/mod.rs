@@ -0,0 +1,169 @@
+use std::path::Path;
+
+use bevy::{asset::FileAssetIo, prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+use self::atlas::{load_tile, BlockAtlas};
+use crate::util::{load_ron, LoadError};
+
+pub mod atlas;
+
+/// Where the block definitions are read from, relative to the asset folder.
+const BLOCK_REGISTRY_PATH: &str = "blocks.ron";
+
+pub struct BlockPlugin;
+
+impl Plugin for BlockPlugin {
+    fn build(&self, app: &mut App) {
+        let asset_folder = FileAssetIo::get_base_path().join("assets");
+
+        let block_registry = BlockRegistry::load(&asset_folder.join(BLOCK_REGISTRY_PATH))
+            .unwrap_or_else(|error| panic!("Could not load the block registry. {}", error));
+
+        let (mut block_atlas, atlas_data) =
+            BlockAtlas::build(&block_registry, |path| load_tile(&asset_folder.join(path)));
+        let atlas_image = block_atlas.create_image(atlas_data);
+        block_atlas.image = app
+            .world
+            .get_resource_mut::<Assets<Image>>()
+            .unwrap()
+            .add(atlas_image);
+
+        app.insert_resource(block_registry)
+            .insert_resource(block_atlas);
+    }
+}
+
+/// Runtime id of a block, which is its index in the [`BlockRegistry`].
+/// Not stable across changes to the block definitions, see [`BlockDefinition::id`] for that.
+#[derive(Debug, Default, Eq, Hash, PartialEq, Copy, Clone, Component, Reflect, FromReflect)]
+pub struct BlockType(pub u16);
+
+/// Describes a single kind of block, as read from the block definitions file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockDefinition {
+    /// Stable, unique name of the block, e.g. `"stone"`.
+    pub id: String,
+    /// Name shown to the player.
+    pub name: String,
+    /// Flat colour of the block, used to tint untextured blocks and wherever
+    /// a single colour has to stand in for the block, like the inventory.
+    pub color: (f32, f32, f32),
+    #[serde(default)]
+    pub textures: Option<BlockTextures>,
+    /// Whether the block can be collided with and targeted.
+    #[serde(default = "default_solid")]
+    pub solid: bool,
+    /// Whether the faces of blocks behind this one remain visible.
+    #[serde(default)]
+    pub transparent: bool,
+    /// How opaque the block is drawn, only blocks that are also `transparent` are blended.
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+    /// Block light the block gives off, up to [`MAX_LIGHT`](crate::chunk::light::MAX_LIGHT).
+    #[serde(default)]
+    pub light: u8,
+    /// Whether the block flows into neighbouring air, see [`crate::chunk::fluid`].
+    #[serde(default)]
+    pub fluid: bool,
+}
+
+/// Texture files of a block, relative to the asset folder.
+#[derive(Debug, Clone, Deserialize)]
+pub enum BlockTextures {
+    /// The same texture on every face.
+    All(String),
+    Faces {
+        top: String,
+        side: String,
+        bottom: String,
+    },
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+impl BlockDefinition {
+    pub fn color(&self) -> Color {
+        Color::rgba(self.color.0, self.color.1, self.color.2, self.opacity)
+    }
+}
+
+/// Every known block, loaded from the block definitions file at startup,
+/// so blocks can be added without recompiling.
+#[derive(Resource, Debug, Clone)]
+pub struct BlockRegistry {
+    blocks: Vec<BlockDefinition>,
+    ids: HashMap<String, BlockType>,
+}
+
+impl BlockRegistry {
+    pub fn load(path: &Path) -> Result<Self, LoadError> {
+        return load_ron(path).map(Self::new);
+    }
+
+    pub fn new(blocks: Vec<BlockDefinition>) -> Self {
+        // region files store ids with a single length byte
+        for block in blocks.iter() {
+            assert!(
+                block.id.len() <= u8::MAX as usize,
+                "Block id {:?} is longer than {} bytes",
+                block.id,
+                u8::MAX
+            );
+        }
+
+        let ids = blocks
+            .iter()
+            .enumerate()
+            .map(|(index, block)| (block.id.clone(), BlockType(index as u16)))
+            .collect();
+
+        return Self { blocks, ids };
+    }
+
+    pub fn get(&self, block_type: BlockType) -> &BlockDefinition {
+        &self.blocks[block_type.0 as usize]
+    }
+
+    /// Looks a block up by its stable id, e.g. `"stone"`.
+    pub fn by_id(&self, id: &str) -> Option<BlockType> {
+        self.ids.get(id).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BlockType, &BlockDefinition)> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .map(|(index, block)| (BlockType(index as u16), block))
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_solid(&self, block_type: BlockType) -> bool {
+        self.get(block_type).solid
+    }
+
+    pub fn is_transparent(&self, block_type: BlockType) -> bool {
+        self.get(block_type).transparent
+    }
+
+    pub fn is_fluid(&self, block_type: BlockType) -> bool {
+        self.get(block_type).fluid
+    }
+}
+
+#[cfg(test)]
+impl BlockRegistry {
+    /// The block definitions shipped in the asset folder, for tests.
+    pub fn from_assets() -> Self {
+        return Self::new(ron::from_str(include_str!("../../assets/blocks.ron")).unwrap());
+    }
+}
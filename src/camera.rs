@@ -1,16 +1,28 @@
-use bevy::prelude::*;
+use bevy::{input::mouse::MouseMotion, prelude::*};
 use smooth_bevy_cameras::{
-    controllers::fps::{FpsCameraBundle, FpsCameraController, FpsCameraPlugin},
+    controllers::fps::{
+        control_system, ControlEvent, FpsCameraBundle, FpsCameraController, FpsCameraPlugin,
+    },
     LookTransformPlugin,
 };
 
+use crate::{input::Action, state::GameState, ui::inventory_screen::inventory_screen_closed};
+
 pub struct CameraControllerPlugin;
 
 impl Plugin for CameraControllerPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(LookTransformPlugin)
-            .add_plugin(FpsCameraPlugin::default())
-            .add_startup_system(create_camera_system);
+            // steered by actions instead of the controller's own fixed keys
+            .add_plugin(FpsCameraPlugin::new(true))
+            .add_startup_system(create_camera_system)
+            .add_system(
+                fly_camera_input
+                    .before(control_system)
+                    // the mouse and keys work the inventory screen instead
+                    .run_if(inventory_screen_closed)
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
     }
 }
 
@@ -19,13 +31,47 @@ fn create_camera_system(mut commands: Commands) {
         .spawn(Camera3dBundle::default())
         .insert(FpsCameraBundle::new(
             FpsCameraController {
+                // the player steers the camera until switching to flying, see `player::MovementMode`
+                enabled: false,
                 translate_sensitivity: 5.0,
                 mouse_rotate_sensitivity: Vec2 { x: 0.15, y: 0.15 },
                 smoothing_weight: 0.0,
-                ..default()
             },
             Vec3::new(5.0, 5.0, 0.0),
             Vec3::new(0.0, 0.0, 0.0),
             Vec3::Y,
         ));
 }
+
+/// Flies the camera around while its controller is enabled.
+fn fly_camera_input(
+    mut events: EventWriter<ControlEvent>,
+    actions: Res<Input<Action>>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    controllers: Query<&FpsCameraController>,
+) {
+    let cursor_delta: Vec2 = mouse_motion.iter().map(|motion| motion.delta).sum();
+
+    let Some(controller) = controllers.iter().find(|controller| controller.enabled) else {
+        return;
+    };
+
+    events.send(ControlEvent::Rotate(
+        controller.mouse_rotate_sensitivity * cursor_delta,
+    ));
+
+    for (action, direction) in [
+        (Action::MoveForward, Vec3::Z),
+        (Action::MoveLeft, Vec3::X),
+        (Action::MoveBack, Vec3::NEG_Z),
+        (Action::MoveRight, Vec3::NEG_X),
+        (Action::FlyDown, Vec3::NEG_Y),
+        (Action::FlyUp, Vec3::Y),
+    ] {
+        if actions.pressed(action) {
+            events.send(ControlEvent::TranslateEye(
+                controller.translate_sensitivity * direction,
+            ));
+        }
+    }
+}
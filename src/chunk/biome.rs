@@ -0,0 +1,193 @@
+use bevy::prelude::*;
+use noise::{NoiseFn, Perlin};
+use serde::Deserialize;
+
+/// Blocks per unit of climate noise input, climates change much slower than the terrain.
+const CLIMATE_SCALE: f64 = 512.0;
+/// How sharply biomes blend into each other, higher values give narrower borders.
+const BLEND_SHARPNESS: i32 = 6;
+
+/// Kinds of terrain, each picked where the climate is closest to its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Biome {
+    Desert,
+    Plains,
+    Hills,
+    Mountains,
+    Tundra,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 5] = [
+        Biome::Desert,
+        Biome::Plains,
+        Biome::Hills,
+        Biome::Mountains,
+        Biome::Tundra,
+    ];
+
+    pub fn parameters(self) -> &'static BiomeParameters {
+        &BIOME_PARAMETERS[self as usize]
+    }
+
+    /// Colour of the biome on the debug biome map.
+    pub fn map_color(self) -> Color {
+        match self {
+            Biome::Desert => Color::rgb(0.9, 0.8, 0.45),
+            Biome::Plains => Color::rgb(0.45, 0.8, 0.3),
+            Biome::Hills => Color::rgb(0.2, 0.55, 0.2),
+            Biome::Mountains => Color::rgb(0.5, 0.5, 0.5),
+            Biome::Tundra => Color::rgb(0.9, 0.95, 1.0),
+        }
+    }
+}
+
+/// How a biome shapes the terrain. Block ids refer to the block definitions.
+#[derive(Debug)]
+pub struct BiomeParameters {
+    /// Climate the biome is found in, as `(temperature, moisture)` in `0.0..=1.0`.
+    pub climate: (f64, f64),
+    /// Topmost block of a column.
+    pub surface: &'static str,
+    /// Blocks right below the surface, before stone starts.
+    pub subsurface: &'static str,
+    pub subsurface_depth: i32,
+    /// Height of the lowest terrain.
+    pub base_height: f64,
+    /// How far the hills rise above the base height.
+    pub height_scale: f64,
+    /// Share of small scale detail in the hills, from `0.0` (smooth) to `1.0`.
+    pub roughness: f64,
+    /// How many blocks 3D noise may push the surface in or out, creating overhangs and cliffs.
+    pub overhang: f64,
+}
+
+const BIOME_PARAMETERS: [BiomeParameters; 5] = [
+    BiomeParameters {
+        climate: (0.9, 0.1),
+        surface: "sand",
+        subsurface: "sand",
+        subsurface_depth: 4,
+        base_height: 6.0,
+        height_scale: 6.0,
+        roughness: 0.1,
+        overhang: 1.0,
+    },
+    BiomeParameters {
+        climate: (0.55, 0.4),
+        surface: "grass",
+        subsurface: "soil",
+        subsurface_depth: 3,
+        base_height: 8.0,
+        height_scale: 8.0,
+        roughness: 0.15,
+        overhang: 1.5,
+    },
+    BiomeParameters {
+        climate: (0.5, 0.85),
+        surface: "grass",
+        subsurface: "soil",
+        subsurface_depth: 3,
+        base_height: 10.0,
+        height_scale: 18.0,
+        roughness: 0.4,
+        overhang: 6.0,
+    },
+    BiomeParameters {
+        climate: (0.25, 0.3),
+        surface: "stone",
+        subsurface: "stone",
+        subsurface_depth: 0,
+        base_height: 14.0,
+        height_scale: 34.0,
+        roughness: 0.5,
+        overhang: 12.0,
+    },
+    BiomeParameters {
+        climate: (0.05, 0.7),
+        surface: "snow",
+        subsurface: "soil",
+        subsurface_depth: 2,
+        base_height: 9.0,
+        height_scale: 10.0,
+        roughness: 0.2,
+        overhang: 2.0,
+    },
+];
+
+/// Temperature and moisture of a column, both in `0.0..=1.0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Climate {
+    pub temperature: f64,
+    pub moisture: f64,
+}
+
+impl Climate {
+    fn distance_squared(&self, (temperature, moisture): (f64, f64)) -> f64 {
+        (self.temperature - temperature).powi(2) + (self.moisture - moisture).powi(2)
+    }
+}
+
+/// Decides the biome of every column from two independent noise fields.
+#[derive(Debug, Clone)]
+pub struct BiomeMap {
+    temperature: Perlin,
+    moisture: Perlin,
+}
+
+impl BiomeMap {
+    pub fn new(seed: u32) -> Self {
+        return Self {
+            temperature: Perlin::new(seed.wrapping_add(1)),
+            moisture: Perlin::new(seed.wrapping_add(2)),
+        };
+    }
+
+    pub fn climate_at(&self, x: i32, z: i32) -> Climate {
+        let point = [x as f64 / CLIMATE_SCALE, z as f64 / CLIMATE_SCALE];
+
+        return Climate {
+            temperature: (self.temperature.get(point) * 0.5 + 0.5).clamp(0.0, 1.0),
+            moisture: (self.moisture.get(point) * 0.5 + 0.5).clamp(0.0, 1.0),
+        };
+    }
+
+    /// The biome whose climate is closest to the column's.
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        let climate = self.climate_at(x, z);
+
+        return Biome::ALL
+            .into_iter()
+            .min_by(|a, b| {
+                let a = climate.distance_squared(a.parameters().climate);
+                let b = climate.distance_squared(b.parameters().climate);
+                a.total_cmp(&b)
+            })
+            .unwrap();
+    }
+
+    /// How much each biome of [`Biome::ALL`] contributes to a column, summing up to 1.
+    /// Close to a biome's climate its weight approaches 1, near borders neighbours mix in.
+    pub fn weights_at(&self, x: i32, z: i32) -> [f64; 5] {
+        let climate = self.climate_at(x, z);
+        let mut weights = [0.0; 5];
+
+        for (weight, biome) in weights.iter_mut().zip(Biome::ALL) {
+            let distance_squared = climate.distance_squared(biome.parameters().climate);
+
+            // right on the biome's climate, nothing else matters
+            if distance_squared < 1e-12 {
+                let mut exact = [0.0; 5];
+                exact[biome as usize] = 1.0;
+                return exact;
+            }
+
+            *weight = 1.0 / distance_squared.powi(BLEND_SHARPNESS / 2);
+        }
+
+        let total: f64 = weights.iter().sum();
+        weights.iter_mut().for_each(|weight| *weight /= total);
+
+        return weights;
+    }
+}
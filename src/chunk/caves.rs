@@ -0,0 +1,87 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+use noise::{NoiseFn, Perlin};
+use serde::Deserialize;
+
+use crate::util::{load_ron, LoadError};
+
+/// Where the cave settings are read from, relative to the asset folder.
+pub const CAVE_SETTINGS_PATH: &str = "caves.ron";
+
+/// Blocks per unit of cheese cave noise input, larger values give larger caverns.
+const CHEESE_SCALE: f64 = 24.0;
+/// Blocks per unit of worm cave noise input, larger values give longer, straighter tunnels.
+const WORM_SCALE: f64 = 48.0;
+/// Caves are squashed vertically by this factor, so they spread out rather than going straight down.
+const VERTICAL_SQUASH: f64 = 1.5;
+
+/// Shape of the caves carved out of the terrain.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum CaveKind {
+    /// No caves at all.
+    None,
+    /// Large open caverns where a noise field exceeds `threshold` (`-1.0..=1.0`, higher carves less).
+    Cheese { threshold: f64 },
+    /// Winding tunnels where two noise fields are both close to zero, `radius` being how close.
+    Worm { radius: f64 },
+}
+
+/// Configures cave generation, read from the cave settings file at startup.
+/// Insert it before adding the [`ChunkPlugin`](super::ChunkPlugin) to use it instead.
+#[derive(Resource, Debug, Clone, Copy, Deserialize)]
+pub struct CaveSettings {
+    pub kind: CaveKind,
+    /// Caves don't carve below this height, deeper down there is only solid stone.
+    pub min_height: i32,
+}
+
+impl CaveSettings {
+    pub fn load(path: &Path) -> Result<Self, LoadError> {
+        return load_ron(path);
+    }
+}
+
+/// Decides which blocks below the surface are carved out.
+#[derive(Debug, Clone)]
+pub struct Caves {
+    settings: CaveSettings,
+    first: Perlin,
+    second: Perlin,
+}
+
+impl Caves {
+    pub fn new(seed: u32, settings: CaveSettings) -> Self {
+        return Self {
+            settings,
+            first: Perlin::new(seed.wrapping_add(10)),
+            second: Perlin::new(seed.wrapping_add(11)),
+        };
+    }
+
+    fn sample(noise: &Perlin, position: IVec3, scale: f64) -> f64 {
+        noise.get([
+            position.x as f64 / scale,
+            position.y as f64 * VERTICAL_SQUASH / scale,
+            position.z as f64 / scale,
+        ])
+    }
+
+    /// Whether the block at a world space position is part of a cave.
+    pub fn is_cave(&self, position: IVec3) -> bool {
+        if position.y < self.settings.min_height {
+            return false;
+        }
+
+        match self.settings.kind {
+            CaveKind::None => false,
+            CaveKind::Cheese { threshold } => {
+                Self::sample(&self.first, position, CHEESE_SCALE) > threshold
+            }
+            CaveKind::Worm { radius } => {
+                Self::sample(&self.first, position, WORM_SCALE).abs() < radius
+                    && Self::sample(&self.second, position, WORM_SCALE).abs() < radius
+            }
+        }
+    }
+}
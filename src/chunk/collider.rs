@@ -0,0 +1,31 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::Collider;
+
+use super::mesh::Quad;
+use crate::block::BlockRegistry;
+
+/// Builds a static trimesh collider out of the same quads the chunk is rendered with,
+/// in chunk-local coordinates, leaving out non-solid blocks.
+/// Returns `None` if there is nothing to collide with.
+pub fn build_collider(quads: &[Quad], block_registry: &BlockRegistry) -> Option<Collider> {
+    let mut vertices: Vec<Vec3> = Vec::with_capacity(quads.len() * 4);
+    let mut indices: Vec<[u32; 3]> = Vec::with_capacity(quads.len() * 2);
+
+    for quad in quads {
+        if !block_registry.is_solid(quad.block_type) {
+            continue;
+        }
+
+        let start = vertices.len() as u32;
+
+        vertices.extend_from_slice(&quad.corners());
+        indices.push([start, start + 1, start + 2]);
+        indices.push([start, start + 2, start + 3]);
+    }
+
+    if indices.is_empty() {
+        return None;
+    }
+
+    return Some(Collider::trimesh(vertices, indices));
+}
@@ -0,0 +1,278 @@
+use bevy::prelude::*;
+
+use super::ChunkRegistry;
+use crate::block::{BlockRegistry, BlockType};
+
+/// Level of fluid sources, which never run dry. Fluid flowing out of them has lower levels.
+pub const SOURCE_LEVEL: u8 = 8;
+/// Level of fluid falling down, so it spreads out again once it lands.
+const FALLING_LEVEL: u8 = SOURCE_LEVEL - 1;
+/// Upper bound of blocks looked at per step, the rest wait for the next one.
+const MAX_UPDATES_PER_STEP: usize = 4096;
+
+const HORIZONTAL_OFFSETS: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+
+/// The fluid at a world space position and its level, `None` for anything else.
+fn fluid_at(
+    chunk_registry: &ChunkRegistry,
+    block_registry: &BlockRegistry,
+    position: IVec3,
+) -> Option<(BlockType, u8)> {
+    let block_type = chunk_registry.get_block(position)?;
+
+    if !block_registry.is_fluid(block_type) {
+        return None;
+    }
+
+    let (chunk, local) = ChunkRegistry::world_to_chunk(position);
+    let level = chunk_registry.get_chunk(chunk)?.fluid_level(local);
+
+    return Some((block_type, level));
+}
+
+/// Fluid only spreads sideways once it rests on something, sources also on top of other fluid.
+fn spreads_sideways(
+    chunk_registry: &ChunkRegistry,
+    block_registry: &BlockRegistry,
+    position: IVec3,
+    level: u8,
+) -> bool {
+    match chunk_registry.get_block(position - IVec3::Y) {
+        Some(below) => level == SOURCE_LEVEL || !block_registry.is_fluid(below),
+        None => false,
+    }
+}
+
+/// What a block that is air or flowing fluid turns into, judging by its neighbours:
+/// fluid above falls in at [`FALLING_LEVEL`], fluid next to it spreads in one level lower.
+fn inflow(
+    chunk_registry: &ChunkRegistry,
+    block_registry: &BlockRegistry,
+    position: IVec3,
+) -> Option<(BlockType, u8)> {
+    if let Some((fluid, _)) = fluid_at(chunk_registry, block_registry, position + IVec3::Y) {
+        return Some((fluid, FALLING_LEVEL));
+    }
+
+    let mut strongest: Option<(BlockType, u8)> = None;
+
+    for offset in HORIZONTAL_OFFSETS {
+        let neighbour = position + offset;
+
+        let Some((fluid, level)) = fluid_at(chunk_registry, block_registry, neighbour) else {
+            continue;
+        };
+
+        if level <= 1 || !spreads_sideways(chunk_registry, block_registry, neighbour, level) {
+            continue;
+        }
+
+        if strongest.is_none_or(|(_, strongest_level)| level - 1 > strongest_level) {
+            strongest = Some((fluid, level - 1));
+        }
+    }
+
+    return strongest;
+}
+
+/// Advances the fluid simulation by one step, looking at the blocks in
+/// [`ChunkRegistry::fluid_updates`]. All blocks are decided on the state before the step
+/// and only then written, so the outcome doesn't depend on the order they are visited in.
+///
+/// Sources stay where they are. Flowing fluid is recomputed from its neighbours every step,
+/// so it fills the air around sources with decreasing levels and runs dry once cut off.
+pub fn step_fluids(chunk_registry: &mut ChunkRegistry, block_registry: &BlockRegistry) {
+    if chunk_registry.fluid_updates.is_empty() {
+        return;
+    }
+
+    let mut positions: Vec<IVec3> = chunk_registry.fluid_updates.drain().collect();
+    // bottom up, so the same blocks are left over for the next step if there are too many
+    positions.sort_by_key(|position| (position.y, position.x, position.z));
+
+    if positions.len() > MAX_UPDATES_PER_STEP {
+        chunk_registry
+            .fluid_updates
+            .extend(positions.drain(MAX_UPDATES_PER_STEP..));
+    }
+
+    let mut changes = Vec::new();
+
+    for position in positions {
+        let (chunk, _) = ChunkRegistry::world_to_chunk(position);
+        if chunk_registry.get_chunk(chunk).is_none() {
+            continue;
+        }
+
+        let current = match chunk_registry.get_block(position) {
+            None => None,
+            Some(block_type) if block_registry.is_fluid(block_type) => {
+                fluid_at(chunk_registry, block_registry, position)
+            }
+            // solid ground and other blocks never change
+            Some(_) => continue,
+        };
+
+        if matches!(current, Some((_, SOURCE_LEVEL))) {
+            continue;
+        }
+
+        let next = inflow(chunk_registry, block_registry, position);
+        if next != current {
+            changes.push((position, next));
+        }
+    }
+
+    // writing a block queues its neighbours for the next step
+    for (position, next) in changes {
+        match next {
+            Some((fluid, level)) => chunk_registry.set_fluid(position, fluid, level),
+            None => chunk_registry.set_block(position, None),
+        };
+    }
+}
+
+/// Runs the fluid simulation at the fixed timestep.
+pub fn flow_fluids(mut chunk_registry: ResMut<ChunkRegistry>, block_registry: Res<BlockRegistry>) {
+    step_fluids(&mut chunk_registry, &block_registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    struct World {
+        chunk_registry: ChunkRegistry,
+        block_registry: BlockRegistry,
+        water: BlockType,
+    }
+
+    impl World {
+        /// A single chunk with a stone floor at the bottom and, if given, a stone wall across it
+        /// at that `x`, with a water source at `source`.
+        fn new(wall: Option<u32>, source: IVec3) -> Self {
+            let block_registry = BlockRegistry::from_assets();
+            let stone = block_registry.by_id("stone").unwrap();
+            let water = block_registry.by_id("water").unwrap();
+
+            let mut chunk = Chunk::empty(IVec3::ZERO);
+            for x in 0..16 {
+                for z in 0..16 {
+                    chunk.set_block(UVec3::new(x, 0, z), Some(stone));
+                }
+            }
+            if let Some(x) = wall {
+                for y in 1..4 {
+                    for z in 0..16 {
+                        chunk.set_block(UVec3::new(x, y, z), Some(stone));
+                    }
+                }
+            }
+
+            let mut chunk_registry = ChunkRegistry::default();
+            chunk_registry.insert(chunk);
+            chunk_registry.set_fluid(source, water, SOURCE_LEVEL);
+
+            return Self {
+                chunk_registry,
+                block_registry,
+                water,
+            };
+        }
+
+        fn step(&mut self, steps: usize) {
+            for _ in 0..steps {
+                step_fluids(&mut self.chunk_registry, &self.block_registry);
+            }
+        }
+
+        fn level(&self, x: i32, y: i32, z: i32) -> Option<u8> {
+            let (fluid, level) = fluid_at(
+                &self.chunk_registry,
+                &self.block_registry,
+                IVec3::new(x, y, z),
+            )?;
+            assert_eq!(fluid, self.water);
+
+            return Some(level);
+        }
+    }
+
+    #[test]
+    fn falls_one_block_per_step() {
+        let mut world = World::new(None, IVec3::new(8, 6, 8));
+
+        world.step(2);
+        assert_eq!(world.level(8, 5, 8), Some(FALLING_LEVEL));
+        assert_eq!(world.level(8, 4, 8), Some(FALLING_LEVEL));
+        assert_eq!(world.level(8, 3, 8), None);
+
+        world.step(3);
+        assert_eq!(world.level(8, 1, 8), Some(FALLING_LEVEL));
+        // falling fluid doesn't spread until it lands
+        assert_eq!(world.level(9, 2, 8), None);
+        assert_eq!(world.level(9, 1, 8), None);
+
+        world.step(1);
+        assert_eq!(world.level(9, 1, 8), Some(FALLING_LEVEL - 1));
+        assert_eq!(world.level(9, 2, 8), None);
+        // the source stays
+        assert_eq!(world.level(8, 6, 8), Some(SOURCE_LEVEL));
+    }
+
+    #[test]
+    fn spreads_one_level_lower_per_block() {
+        let mut world = World::new(None, IVec3::new(8, 1, 8));
+
+        world.step(1);
+        assert_eq!(world.level(9, 1, 8), Some(SOURCE_LEVEL - 1));
+        assert_eq!(world.level(10, 1, 8), None);
+
+        world.step(10);
+        for distance in 1..SOURCE_LEVEL as i32 {
+            assert_eq!(
+                world.level(8 + distance, 1, 8),
+                Some(SOURCE_LEVEL - distance as u8)
+            );
+            assert_eq!(
+                world.level(8, 1, 8 - distance),
+                Some(SOURCE_LEVEL - distance as u8)
+            );
+        }
+        assert_eq!(world.level(10, 1, 10), Some(SOURCE_LEVEL - 4));
+        // the last level doesn't spread any further
+        assert_eq!(world.level(8, 1, 0), None);
+        assert_eq!(world.level(8, 2, 8), None);
+        assert!(world.chunk_registry.fluid_updates.is_empty());
+    }
+
+    #[test]
+    fn solid_blocks_stop_it() {
+        let mut world = World::new(Some(10), IVec3::new(8, 1, 8));
+
+        world.step(20);
+        assert_eq!(world.level(9, 1, 8), Some(SOURCE_LEVEL - 1));
+        assert_eq!(world.level(9, 1, 14), Some(SOURCE_LEVEL - 7));
+        for x in 11..16 {
+            for z in 0..16 {
+                assert_eq!(world.level(x, 1, z), None);
+            }
+        }
+    }
+
+    #[test]
+    fn runs_dry_without_its_source() {
+        let mut world = World::new(None, IVec3::new(8, 1, 8));
+
+        world.step(10);
+        world.chunk_registry.set_block(IVec3::new(8, 1, 8), None);
+        world.step(20);
+
+        for x in 0..16 {
+            for z in 0..16 {
+                assert_eq!(world.level(x, 1, z), None);
+            }
+        }
+    }
+}
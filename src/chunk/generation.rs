@@ -0,0 +1,466 @@
+use std::sync::Arc;
+
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use noise::{Fbm, NoiseFn, Perlin};
+
+use super::{
+    biome::{Biome, BiomeMap},
+    caves::{CaveSettings, Caves},
+    structure::{column_random, StructureSpill, StructureTemplate},
+    Chunk, ChunkRegistry, CHUNK_DIMENSIONS, CHUNK_SIZE,
+};
+use crate::block::{BlockRegistry, BlockType};
+
+/// Blocks per unit of noise input, larger values stretch the hills.
+const TERRAIN_SCALE: f64 = 128.0;
+/// Blocks per unit of the detail noise mixed into the hills by rough biomes.
+const DETAIL_SCALE: f64 = 24.0;
+/// Blocks per unit of the 3D noise bending the surface into overhangs.
+const OVERHANG_SCALE: f64 = 12.0;
+/// Air at or below this height is filled with water.
+pub const SEA_LEVEL: i32 = 9;
+/// Side length in blocks of the area around the origin shown by the debug overlays.
+const HEIGHTMAP_OVERLAY_SIZE: usize = 256;
+/// Height shown as white on the heightmap overlay.
+const HEIGHTMAP_OVERLAY_MAX_HEIGHT: f32 = 64.0;
+
+/// Seed of all terrain noise, the same seed always generates the same world.
+/// Insert it before adding the [`ChunkPlugin`](super::ChunkPlugin) to pick a different world.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorldSeed(pub u32);
+
+impl Default for WorldSeed {
+    fn default() -> Self {
+        Self(999)
+    }
+}
+
+/// Blocks a biome is made of, looked up once so generation doesn't need the registry.
+#[derive(Debug, Clone, Copy)]
+struct BiomeBlocks {
+    surface: BlockType,
+    subsurface: BlockType,
+}
+
+/// A single generated column of terrain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerrainColumn {
+    /// Height of the surface before overhangs are applied.
+    pub height: i32,
+    pub biome: Biome,
+    /// How far overhangs may move the surface up or down, see [`BiomeParameters::overhang`](super::biome::BiomeParameters::overhang).
+    pub overhang: f64,
+}
+
+/// Generates terrain by sampling noise per block column, so any chunk coordinate can be
+/// generated on its own, including negative ones.
+/// Cheap to clone, generation tasks get their own copy.
+#[derive(Resource, Clone)]
+pub struct TerrainGenerator {
+    seed: u32,
+    hills: Arc<Fbm<Perlin>>,
+    detail: Perlin,
+    overhangs: Perlin,
+    biome_map: BiomeMap,
+    caves: Caves,
+    stone: BlockType,
+    /// Covers the ground under water and right above it.
+    sand: BlockType,
+    water: BlockType,
+    /// Indexed like [`Biome::ALL`].
+    biome_blocks: Vec<BiomeBlocks>,
+    structures: Arc<Vec<StructureTemplate>>,
+}
+
+impl TerrainGenerator {
+    pub fn new(
+        seed: WorldSeed,
+        cave_settings: CaveSettings,
+        structures: Vec<StructureTemplate>,
+        block_registry: &BlockRegistry,
+    ) -> Self {
+        let block = |id: &str| {
+            block_registry
+                .by_id(id)
+                .unwrap_or_else(|| panic!("Terrain generation needs a {:?} block", id))
+        };
+
+        let biome_blocks = Biome::ALL
+            .iter()
+            .map(|biome| BiomeBlocks {
+                surface: block(biome.parameters().surface),
+                subsurface: block(biome.parameters().subsurface),
+            })
+            .collect();
+
+        return Self {
+            seed: seed.0,
+            hills: Arc::new(Fbm::<Perlin>::new(seed.0)),
+            detail: Perlin::new(seed.0.wrapping_add(3)),
+            overhangs: Perlin::new(seed.0.wrapping_add(4)),
+            biome_map: BiomeMap::new(seed.0),
+            caves: Caves::new(seed.0, cave_settings),
+            stone: block("stone"),
+            sand: block("sand"),
+            water: block("water"),
+            biome_blocks,
+            structures: Arc::new(structures),
+        };
+    }
+
+    pub fn biome_map(&self) -> &BiomeMap {
+        &self.biome_map
+    }
+
+    /// Hills at a world space column, mapped to `0.0..=1.0`.
+    fn hills(&self, x: i32, z: i32) -> f64 {
+        let value = self
+            .hills
+            .get([x as f64 / TERRAIN_SCALE, z as f64 / TERRAIN_SCALE]);
+
+        return (value * 0.5 + 0.5).clamp(0.0, 1.0);
+    }
+
+    /// The terrain of a world space column. Its height blends the shapes of all biomes
+    /// by their weight, so there are no cliffs at biome borders.
+    pub fn column(&self, x: i32, z: i32) -> TerrainColumn {
+        let hills = self.hills(x, z);
+        let detail = (self
+            .detail
+            .get([x as f64 / DETAIL_SCALE, z as f64 / DETAIL_SCALE])
+            * 0.5
+            + 0.5)
+            .clamp(0.0, 1.0);
+        let weights = self.biome_map.weights_at(x, z);
+
+        let mut height = 0.0;
+        let mut overhang = 0.0;
+        for (biome, weight) in Biome::ALL.iter().zip(weights) {
+            let parameters = biome.parameters();
+            let shape = hills * (1.0 - parameters.roughness) + detail * parameters.roughness;
+            height += weight * (parameters.base_height + parameters.height_scale * shape);
+            overhang += weight * parameters.overhang;
+        }
+
+        return TerrainColumn {
+            height: height.round() as i32,
+            biome: self.biome_map.biome_at(x, z),
+            overhang,
+        };
+    }
+
+    /// Height of the topmost block of a world space column.
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        self.column(x, z).height
+    }
+
+    /// Highest a world space column's terrain can reach, overhangs included.
+    /// Generated terrain leaves everything above it open to the sky.
+    pub fn terrain_top_at(&self, x: i32, z: i32) -> i32 {
+        let column = self.column(x, z);
+
+        return column.height + column.overhang.ceil() as i32;
+    }
+
+    /// Whether the terrain at a world space position is solid, before caves are carved.
+    /// Far from the surface that follows from the height alone, close to it
+    /// 3D noise decides, which lets the surface fold over itself.
+    fn is_terrain(&self, column: &TerrainColumn, position: IVec3) -> bool {
+        let depth = (column.height - position.y) as f64;
+
+        if depth.abs() > column.overhang {
+            return depth >= 0.0;
+        }
+
+        let bend = self.overhangs.get([
+            position.x as f64 / OVERHANG_SCALE,
+            position.y as f64 / OVERHANG_SCALE,
+            position.z as f64 / OVERHANG_SCALE,
+        ]);
+
+        return depth + bend * column.overhang >= 0.0;
+    }
+
+    /// Fills the chunk at `position` (in chunk coordinates) with stone, covered by the surface
+    /// blocks of each column's biome wherever there is air above, or sand close to the sea,
+    /// floods everything up to [`SEA_LEVEL`], carves caves into it and places structures on top.
+    /// Structure blocks outside of the chunk are returned.
+    pub fn generate(&self, position: IVec3) -> (Chunk, StructureSpill) {
+        let mut chunk = Chunk::empty(position);
+        let origin = chunk.world_origin();
+        let mut columns = Vec::with_capacity(CHUNK_SIZE * CHUNK_SIZE);
+
+        for x in 0..CHUNK_SIZE as i32 {
+            for z in 0..CHUNK_SIZE as i32 {
+                let column = self.column(origin.x + x, origin.z + z);
+                columns.push((origin.x + x, origin.z + z, column));
+
+                // the whole chunk is above the surface and the sea
+                if (origin.y as f64) > column.height as f64 + column.overhang
+                    && origin.y > SEA_LEVEL
+                {
+                    continue;
+                }
+
+                let biome_blocks = self.biome_blocks[column.biome as usize];
+                let shore_blocks = BiomeBlocks {
+                    surface: self.sand,
+                    subsurface: self.sand,
+                };
+                let subsurface_depth = column.biome.parameters().subsurface_depth;
+
+                // solid blocks since the last air above, decides between surface and stone
+                let mut depth = 0;
+                let mut blocks = biome_blocks;
+
+                // start above the chunk, the blocks there decide what is surface inside it
+                for y in (0..CHUNK_SIZE as i32 + subsurface_depth + 1).rev() {
+                    let world = origin + IVec3::new(x, y, z);
+
+                    if !self.is_terrain(&column, world) {
+                        depth = 0;
+
+                        if y < CHUNK_SIZE as i32 && world.y <= SEA_LEVEL {
+                            chunk.set_block(
+                                UVec3::new(x as u32, y as u32, z as u32),
+                                Some(self.water),
+                            );
+                        }
+
+                        continue;
+                    }
+
+                    if depth == 0 {
+                        blocks = if world.y <= SEA_LEVEL + 1 {
+                            shore_blocks
+                        } else {
+                            biome_blocks
+                        };
+                    }
+
+                    let block_type = if depth == 0 {
+                        blocks.surface
+                    } else if depth <= subsurface_depth {
+                        blocks.subsurface
+                    } else {
+                        self.stone
+                    };
+                    depth += 1;
+
+                    if y >= CHUNK_SIZE as i32 {
+                        continue;
+                    }
+
+                    // caves are carved after the surface was decided, so their walls stay stone
+                    if self.caves.is_cave(world) {
+                        continue;
+                    }
+
+                    chunk.set_block(UVec3::new(x as u32, y as u32, z as u32), Some(block_type));
+                }
+            }
+        }
+
+        // after all terrain is in place, so structures only fill air
+        let mut spill = StructureSpill::new();
+        for (x, z, column) in columns {
+            self.place_structures(&mut chunk, &mut spill, &column, x, z);
+        }
+
+        return (chunk, spill);
+    }
+
+    /// Places the structure growing on a column, if the column's surface lies in the chunk.
+    /// Each column decides on its own whether it gets a structure, so the result does not
+    /// depend on the order chunks are generated in.
+    fn place_structures(
+        &self,
+        chunk: &mut Chunk,
+        spill: &mut StructureSpill,
+        column: &TerrainColumn,
+        x: i32,
+        z: i32,
+    ) {
+        let base = IVec3::new(x, column.height + 1, z);
+        let (base_chunk, _) = ChunkRegistry::world_to_chunk(base);
+
+        if base_chunk != chunk.position || base.y <= SEA_LEVEL {
+            return;
+        }
+
+        for (index, structure) in self.structures.iter().enumerate() {
+            if !structure.biomes.contains(&column.biome)
+                || column_random(self.seed, x, z, index as u32) >= structure.density
+            {
+                continue;
+            }
+
+            // overhangs and caves may have moved the surface
+            let ground = base - IVec3::Y;
+            if self.is_terrain(column, base)
+                || !self.is_terrain(column, ground)
+                || self.caves.is_cave(ground)
+            {
+                return;
+            }
+
+            for (offset, block_type) in structure.blocks.iter() {
+                let position = base + *offset;
+                let local = position - chunk.world_origin();
+
+                if (local.cmplt(IVec3::ZERO) | local.cmpge(CHUNK_DIMENSIONS)).any() {
+                    spill.push((position, *block_type));
+                    continue;
+                }
+
+                if chunk.get_block(local.as_uvec3()).is_none() {
+                    chunk.set_block(local.as_uvec3(), Some(*block_type));
+                }
+            }
+
+            // one structure per column
+            return;
+        }
+    }
+}
+
+/// Renders an image of the area around the origin, one pixel per column.
+fn render_overlay(
+    images: &mut Assets<Image>,
+    color_at: impl Fn(i32, i32) -> Color,
+) -> Handle<Image> {
+    let half_size = HEIGHTMAP_OVERLAY_SIZE as i32 / 2;
+    let mut rgba_data = Vec::with_capacity(HEIGHTMAP_OVERLAY_SIZE * HEIGHTMAP_OVERLAY_SIZE * 4);
+
+    for z in -half_size..half_size {
+        for x in -half_size..half_size {
+            let color = color_at(x, z).as_rgba_f32();
+            rgba_data.extend(color.map(|channel| (channel * 255.0) as u8));
+        }
+    }
+
+    return images.add(Image::new(
+        Extent3d {
+            width: HEIGHTMAP_OVERLAY_SIZE as u32,
+            height: HEIGHTMAP_OVERLAY_SIZE as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        rgba_data,
+        TextureFormat::Rgba8UnormSrgb,
+    ));
+}
+
+/// Shows the terrain height around the origin, brighter being higher,
+/// and below it the biome map of the same area.
+pub fn spawn_heightmap_overlay(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    terrain_generator: Res<TerrainGenerator>,
+) {
+    let heightmap = render_overlay(&mut images, |x, z| {
+        let height = terrain_generator.height_at(x, z) as f32 / HEIGHTMAP_OVERLAY_MAX_HEIGHT;
+        Color::rgb(height, height, height)
+    });
+
+    let biome_map = render_overlay(&mut images, |x, z| {
+        terrain_generator.biome_map().biome_at(x, z).map_color()
+    });
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::Column,
+                gap: Size::all(Val::Px(10.0)),
+                position: UiRect {
+                    right: Val::Px(10.0),
+                    top: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|container| {
+            for image in [heightmap, biome_map] {
+                container.spawn(ImageBundle {
+                    style: Style {
+                        size: Size::all(Val::Px(200.0)),
+                        ..default()
+                    },
+                    image: UiImage::new(image),
+                    ..default()
+                });
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::caves::CaveKind;
+
+    fn generator(seed: u32) -> TerrainGenerator {
+        let cave_settings = CaveSettings {
+            kind: CaveKind::Worm { radius: 0.06 },
+            min_height: -256,
+        };
+
+        return TerrainGenerator::new(
+            WorldSeed(seed),
+            cave_settings,
+            Vec::new(),
+            &BlockRegistry::from_assets(),
+        );
+    }
+
+    const COLUMNS: [(i32, i32); 4] = [(0, 0), (-3, 5), (-100, -77), (12345, -6789)];
+    const CHUNKS: [IVec3; 4] = [
+        IVec3::ZERO,
+        IVec3::new(-1, 0, 2),
+        IVec3::new(7, -1, -13),
+        IVec3::new(-40, 1, 40),
+    ];
+
+    #[test]
+    fn same_seed_generates_the_same_columns() {
+        let first = generator(7);
+        let second = generator(7);
+
+        for (x, z) in COLUMNS {
+            assert_eq!(first.column(x, z), second.column(x, z));
+            // and asking twice doesn't change anything either
+            assert_eq!(first.column(x, z), first.column(x, z));
+        }
+
+        for position in CHUNKS {
+            let (first_chunk, _) = first.generate(position);
+            let (second_chunk, _) = second.generate(position);
+
+            assert!(first_chunk.blocks == second_chunk.blocks, "{}", position);
+        }
+    }
+
+    #[test]
+    fn different_seeds_generate_different_terrain() {
+        let first = generator(7);
+        let second = generator(8);
+
+        let differing = (-50..50)
+            .filter(|x| first.height_at(*x, x * 3) != second.height_at(*x, x * 3))
+            .count();
+        assert!(differing > 0);
+
+        let differing = CHUNKS
+            .iter()
+            .filter(|position| {
+                first.generate(**position).0.blocks != second.generate(**position).0.blocks
+            })
+            .count();
+        assert!(differing > 0);
+    }
+}
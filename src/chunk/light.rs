@@ -0,0 +1,482 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use super::{
+    generation::TerrainGenerator, ChunkRegistry, CHUNK_DIMENSIONS, CHUNK_SIZE, NEIGHBOUR_OFFSETS,
+};
+use crate::block::BlockRegistry;
+
+/// Brightest light level, that of the open sky.
+pub const MAX_LIGHT: u8 = 15;
+/// Brightness of a block in complete darkness, so caves aren't pitch black.
+const MIN_BRIGHTNESS: f32 = 0.05;
+/// How much darker each light level is than the one above it.
+const LIGHT_FALLOFF: f32 = 0.8;
+
+/// Light of a single block, both channels from `0` (dark) to [`MAX_LIGHT`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Light {
+    /// Light coming from the sky, which travels straight down without getting weaker.
+    pub sky: u8,
+    /// Light given off by blocks, see [`BlockDefinition::light`](crate::block::BlockDefinition::light).
+    pub block: u8,
+}
+
+impl Light {
+    /// Light assumed where nothing is known, like in chunks that aren't loaded.
+    pub const SKY: Light = Light {
+        sky: MAX_LIGHT,
+        block: 0,
+    };
+
+    /// How bright a face lit by this light is drawn, from `0.0` to `1.0`.
+    pub fn brightness(self) -> f32 {
+        let level = self.sky.max(self.block);
+
+        return MIN_BRIGHTNESS
+            + (1.0 - MIN_BRIGHTNESS) * LIGHT_FALLOFF.powi((MAX_LIGHT - level) as i32);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Channel {
+    Sky,
+    Block,
+}
+
+/// Flood fill of one light channel over the loaded chunks. Light spreads to the six neighbours
+/// of a block, one level weaker each step, and doesn't enter opaque blocks.
+/// Removing light first darkens everything the removed light reached,
+/// then lets the remaining light flow back in.
+struct LightFill<'a> {
+    chunk_registry: &'a mut ChunkRegistry,
+    block_registry: &'a BlockRegistry,
+    /// Height of the surface of a column, see [`TerrainGenerator::terrain_top_at`].
+    terrain_top: &'a dyn Fn(i32, i32) -> i32,
+    channel: Channel,
+    spread: VecDeque<IVec3>,
+    remove: VecDeque<(IVec3, u8)>,
+}
+
+impl<'a> LightFill<'a> {
+    fn new(
+        chunk_registry: &'a mut ChunkRegistry,
+        block_registry: &'a BlockRegistry,
+        terrain_top: &'a dyn Fn(i32, i32) -> i32,
+        channel: Channel,
+    ) -> Self {
+        return Self {
+            chunk_registry,
+            block_registry,
+            terrain_top,
+            channel,
+            spread: VecDeque::new(),
+            remove: VecDeque::new(),
+        };
+    }
+
+    /// Light level at a world space position, `None` if its chunk is not loaded.
+    fn get(&self, position: IVec3) -> Option<u8> {
+        let light = self.chunk_registry.get_light(position)?;
+
+        return Some(match self.channel {
+            Channel::Sky => light.sky,
+            Channel::Block => light.block,
+        });
+    }
+
+    fn set(&mut self, position: IVec3, level: u8) {
+        let (chunk, local) = ChunkRegistry::world_to_chunk(position);
+
+        let Some(target) = self.chunk_registry.get_chunk_mut(chunk) else {
+            return;
+        };
+
+        let mut light = target.get_light(local);
+        match self.channel {
+            Channel::Sky => light.sky = level,
+            Channel::Block => light.block = level,
+        }
+
+        target.set_light(local, light);
+        self.chunk_registry.mark_dirty(chunk, local);
+    }
+
+    fn is_opaque(&self, position: IVec3) -> bool {
+        match self.chunk_registry.get_block(position) {
+            Some(block_type) => !self.block_registry.is_transparent(block_type),
+            None => false,
+        }
+    }
+
+    /// Light a block gives off on its own. For the sky channel that is the top of a chunk
+    /// with no loaded chunk above it, but only above the terrain surface,
+    /// so chunks deep underground don't light their caves.
+    fn source(&self, position: IVec3) -> u8 {
+        match self.channel {
+            Channel::Block => self
+                .chunk_registry
+                .get_block(position)
+                .map_or(0, |block_type| self.block_registry.get(block_type).light),
+            Channel::Sky => {
+                let (chunk, local) = ChunkRegistry::world_to_chunk(position);
+
+                if local.y as usize == CHUNK_SIZE - 1
+                    && self.chunk_registry.get_chunk(chunk + IVec3::Y).is_none()
+                    && !self.is_opaque(position)
+                    && position.y > (self.terrain_top)(position.x, position.z)
+                {
+                    MAX_LIGHT
+                } else {
+                    0
+                }
+            }
+        }
+    }
+
+    /// Lights a block with its own light, if it gives off any.
+    fn seed(&mut self, position: IVec3) {
+        let level = self.source(position);
+
+        if level > 0 && self.get(position).is_some_and(|current| current < level) {
+            self.set(position, level);
+            self.spread.push_back(position);
+        }
+    }
+
+    /// Level light of `level` has after moving by `offset`. Sky light at full strength
+    /// keeps it going down, so columns open to the sky are fully lit.
+    fn attenuate(&self, level: u8, offset: IVec3) -> u8 {
+        if self.channel == Channel::Sky && offset == IVec3::NEG_Y && level == MAX_LIGHT {
+            return MAX_LIGHT;
+        }
+
+        return level.saturating_sub(1);
+    }
+
+    /// Darkens everything lit by the queued removals. Blocks lit from elsewhere are
+    /// queued to spread their light back into the darkened area.
+    fn remove_light(&mut self) {
+        while let Some((position, level)) = self.remove.pop_front() {
+            for offset in NEIGHBOUR_OFFSETS {
+                let neighbour = position + offset;
+
+                let Some(neighbour_level) = self.get(neighbour) else {
+                    continue;
+                };
+
+                if neighbour_level == 0 {
+                    continue;
+                }
+
+                if neighbour_level <= self.attenuate(level, offset) {
+                    // lit by the removed light
+                    self.set(neighbour, 0);
+                    self.remove.push_back((neighbour, neighbour_level));
+                    self.seed(neighbour);
+                } else {
+                    self.spread.push_back(neighbour);
+                }
+            }
+        }
+    }
+
+    /// Spreads the queued light to every block it can reach.
+    fn spread_light(&mut self) {
+        while let Some(position) = self.spread.pop_front() {
+            let Some(level) = self.get(position) else {
+                continue;
+            };
+
+            for offset in NEIGHBOUR_OFFSETS {
+                let neighbour = position + offset;
+                let spread = self.attenuate(level, offset);
+
+                if spread == 0 || self.is_opaque(neighbour) {
+                    continue;
+                }
+
+                if self.get(neighbour).is_some_and(|current| current < spread) {
+                    self.set(neighbour, spread);
+                    self.spread.push_back(neighbour);
+                }
+            }
+        }
+    }
+
+    /// Relights the area around a block that changed.
+    fn update_block(&mut self, position: IVec3) {
+        let Some(level) = self.get(position) else {
+            return;
+        };
+
+        self.set(position, 0);
+        self.remove.push_back((position, level));
+        self.remove_light();
+
+        self.seed(position);
+        self.spread_light();
+    }
+
+    /// Lights a chunk that was just added, from its own light sources and the light
+    /// of its loaded neighbours.
+    fn light_chunk(&mut self, chunk: IVec3) {
+        let origin = ChunkRegistry::chunk_to_world(chunk, IVec3::ZERO);
+        let size = CHUNK_SIZE as i32;
+
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    let local = IVec3::new(x, y, z);
+                    self.seed(origin + local);
+
+                    // light of loaded neighbours flows in over the shared faces
+                    for offset in NEIGHBOUR_OFFSETS {
+                        let beyond = local + offset;
+
+                        if (beyond.cmplt(IVec3::ZERO) | beyond.cmpge(CHUNK_DIMENSIONS)).any() {
+                            self.spread.push_back(origin + beyond);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.spread_light();
+
+        // the chunk below took the sky above it to be open, which it may not be anymore
+        if self.channel == Channel::Sky && self.chunk_registry.get_chunk(chunk - IVec3::Y).is_some()
+        {
+            for x in 0..size {
+                for z in 0..size {
+                    let bottom = origin + IVec3::new(x, 0, z);
+                    let below = bottom - IVec3::Y;
+
+                    if self.get(below) == Some(MAX_LIGHT) && self.get(bottom) != Some(MAX_LIGHT) {
+                        self.set(below, 0);
+                        self.remove.push_back((below, MAX_LIGHT));
+                    }
+                }
+            }
+
+            self.remove_light();
+            self.spread_light();
+        }
+    }
+}
+
+/// Brings the light of the loaded chunks up to date with the blocks, lighting chunks added
+/// since the last call and relighting around blocks that changed.
+/// Chunks whose light changed are scheduled for a rebuild, the light is baked into their meshes.
+/// `terrain_top` gives the surface height of a column, above which the sky is open.
+pub fn update_light(
+    chunk_registry: &mut ChunkRegistry,
+    block_registry: &BlockRegistry,
+    terrain_top: impl Fn(i32, i32) -> i32,
+) {
+    if chunk_registry.unlit.is_empty() && chunk_registry.light_updates.is_empty() {
+        return;
+    }
+
+    // sorted, so the outcome doesn't depend on the order of the sets
+    let mut chunks: Vec<IVec3> = chunk_registry.unlit.drain().collect();
+    chunks.sort_by_key(|position| (position.y, position.x, position.z));
+    let mut positions: Vec<IVec3> = chunk_registry.light_updates.drain().collect();
+    positions.sort_by_key(|position| (position.y, position.x, position.z));
+
+    for channel in [Channel::Sky, Channel::Block] {
+        let mut fill = LightFill::new(chunk_registry, block_registry, &terrain_top, channel);
+
+        for chunk in chunks.iter() {
+            if fill.chunk_registry.get_chunk(*chunk).is_some() {
+                fill.light_chunk(*chunk);
+            }
+        }
+
+        for position in positions.iter() {
+            fill.update_block(*position);
+        }
+    }
+}
+
+/// Updates the light before chunks are meshed.
+pub fn light_chunks(
+    mut chunk_registry: ResMut<ChunkRegistry>,
+    block_registry: Res<BlockRegistry>,
+    terrain_generator: Res<TerrainGenerator>,
+) {
+    update_light(&mut chunk_registry, &block_registry, |x, z| {
+        terrain_generator.terrain_top_at(x, z)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockType;
+    use crate::chunk::Chunk;
+
+    /// Surface far above the chunks, like deep underground.
+    fn underground(_: i32, _: i32) -> i32 {
+        return 100;
+    }
+
+    /// Surface below the chunks, everything is open to the sky.
+    fn above_ground(_: i32, _: i32) -> i32 {
+        return -100;
+    }
+
+    fn stone() -> (BlockRegistry, BlockType) {
+        let block_registry = BlockRegistry::from_assets();
+        let stone = block_registry.by_id("stone").unwrap();
+
+        return (block_registry, stone);
+    }
+
+    /// A chunk at the origin filled with `block`, except for the air at `holes`.
+    fn chunk(block: Option<BlockType>, holes: &[IVec3]) -> Chunk {
+        let mut chunk = Chunk::empty(IVec3::ZERO);
+        for x in 0..CHUNK_SIZE as u32 {
+            for y in 0..CHUNK_SIZE as u32 {
+                for z in 0..CHUNK_SIZE as u32 {
+                    chunk.set_block(UVec3::new(x, y, z), block);
+                }
+            }
+        }
+        for hole in holes {
+            chunk.set_block(hole.as_uvec3(), None);
+        }
+
+        return chunk;
+    }
+
+    fn light(chunk_registry: &ChunkRegistry, x: i32, y: i32, z: i32) -> Light {
+        return chunk_registry.get_light(IVec3::new(x, y, z)).unwrap();
+    }
+
+    #[test]
+    fn open_sky_lights_every_column_fully() {
+        let (block_registry, _) = stone();
+        let mut chunk_registry = ChunkRegistry::default();
+        chunk_registry.insert(chunk(None, &[]));
+
+        update_light(&mut chunk_registry, &block_registry, above_ground);
+
+        for (x, y, z) in [(0, 15, 0), (8, 8, 8), (15, 0, 3)] {
+            assert_eq!(light(&chunk_registry, x, y, z), Light::SKY);
+        }
+    }
+
+    #[test]
+    fn caves_below_the_surface_stay_dark() {
+        let (block_registry, stone) = stone();
+        // a cave reaching up to the top of the chunk, with nothing loaded above it
+        let cave: Vec<IVec3> = (12..16)
+            .flat_map(|y| (6..10).map(move |x| IVec3::new(x, y, 8)))
+            .collect();
+
+        let mut chunk_registry = ChunkRegistry::default();
+        chunk_registry.insert(chunk(Some(stone), &cave));
+        update_light(&mut chunk_registry, &block_registry, underground);
+
+        for position in cave.iter() {
+            assert_eq!(chunk_registry.get_light(*position), Some(Light::default()));
+        }
+
+        // the same cave right at the surface is open to the sky
+        let mut chunk_registry = ChunkRegistry::default();
+        chunk_registry.insert(chunk(Some(stone), &cave));
+        update_light(&mut chunk_registry, &block_registry, |_, _| 0);
+
+        for position in cave.iter() {
+            assert_eq!(chunk_registry.get_light(*position), Some(Light::SKY));
+        }
+    }
+
+    #[test]
+    fn sky_light_goes_down_shafts_and_fades_sideways() {
+        let (block_registry, stone) = stone();
+        let mut holes: Vec<IVec3> = (2..16).map(|y| IVec3::new(8, y, 8)).collect();
+        holes.extend((9..13).map(|x| IVec3::new(x, 2, 8)));
+
+        let mut chunk_registry = ChunkRegistry::default();
+        chunk_registry.insert(chunk(Some(stone), &holes));
+        update_light(&mut chunk_registry, &block_registry, |_, _| 0);
+
+        assert_eq!(light(&chunk_registry, 8, 2, 8).sky, MAX_LIGHT);
+        for x in 9..13 {
+            assert_eq!(
+                light(&chunk_registry, x, 2, 8).sky,
+                MAX_LIGHT - (x - 8) as u8
+            );
+        }
+        assert_eq!(light(&chunk_registry, 8, 1, 8).sky, 0);
+    }
+
+    #[test]
+    fn block_light_fades_with_distance_and_goes_out() {
+        let (block_registry, _) = stone();
+        let lamp = block_registry.by_id("lamp").unwrap();
+        let mut lit = chunk(None, &[]);
+        lit.set_block(UVec3::new(8, 8, 8), Some(lamp));
+
+        let mut chunk_registry = ChunkRegistry::default();
+        chunk_registry.insert(lit);
+        update_light(&mut chunk_registry, &block_registry, underground);
+
+        assert_eq!(light(&chunk_registry, 8, 8, 8).block, MAX_LIGHT);
+        assert_eq!(light(&chunk_registry, 9, 8, 8).block, MAX_LIGHT - 1);
+        assert_eq!(light(&chunk_registry, 8, 4, 8).block, MAX_LIGHT - 4);
+        assert_eq!(light(&chunk_registry, 10, 9, 7).block, MAX_LIGHT - 4);
+        assert_eq!(light(&chunk_registry, 0, 0, 0).block, 0);
+        assert_eq!(light(&chunk_registry, 8, 8, 8).sky, 0);
+
+        chunk_registry.set_block(IVec3::new(8, 8, 8), None);
+        update_light(&mut chunk_registry, &block_registry, underground);
+
+        for (x, y, z) in [(8, 8, 8), (9, 8, 8), (10, 9, 7)] {
+            assert_eq!(light(&chunk_registry, x, y, z), Light::default());
+        }
+    }
+
+    #[test]
+    fn placed_blocks_cast_shadows_until_broken() {
+        let (block_registry, stone) = stone();
+        let mut chunk_registry = ChunkRegistry::default();
+        chunk_registry.insert(chunk(None, &[]));
+        update_light(&mut chunk_registry, &block_registry, above_ground);
+
+        chunk_registry.set_block(IVec3::new(8, 10, 8), Some(stone));
+        update_light(&mut chunk_registry, &block_registry, above_ground);
+
+        // lit from the open columns next to it
+        assert_eq!(light(&chunk_registry, 8, 9, 8).sky, MAX_LIGHT - 1);
+        assert_eq!(light(&chunk_registry, 8, 0, 8).sky, MAX_LIGHT - 1);
+        assert_eq!(light(&chunk_registry, 8, 11, 8).sky, MAX_LIGHT);
+
+        chunk_registry.set_block(IVec3::new(8, 10, 8), None);
+        update_light(&mut chunk_registry, &block_registry, above_ground);
+
+        assert_eq!(light(&chunk_registry, 8, 9, 8).sky, MAX_LIGHT);
+        assert_eq!(light(&chunk_registry, 8, 0, 8).sky, MAX_LIGHT);
+    }
+
+    #[test]
+    fn loading_a_solid_chunk_above_darkens_the_one_below() {
+        let (block_registry, stone) = stone();
+        let mut chunk_registry = ChunkRegistry::default();
+        chunk_registry.insert(chunk(None, &[]));
+        update_light(&mut chunk_registry, &block_registry, above_ground);
+        assert_eq!(light(&chunk_registry, 8, 8, 8), Light::SKY);
+
+        let mut roof = chunk(Some(stone), &[]);
+        roof.position = IVec3::Y;
+        chunk_registry.insert(roof);
+        update_light(&mut chunk_registry, &block_registry, above_ground);
+
+        for (x, y, z) in [(0, 15, 0), (8, 8, 8), (15, 0, 3)] {
+            assert_eq!(light(&chunk_registry, x, y, z).sky, 0);
+        }
+    }
+}
@@ -0,0 +1,615 @@
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+
+use super::{light::Light, Chunk, CHUNK_DIMENSIONS};
+use crate::{
+    block::{atlas::BlockAtlas, BlockRegistry, BlockType},
+    material::ATTRIBUTE_TILE_RECT,
+};
+
+/// The six axis-aligned faces of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Face {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::PosX,
+        Face::NegX,
+        Face::PosY,
+        Face::NegY,
+        Face::PosZ,
+        Face::NegZ,
+    ];
+
+    pub fn normal(self) -> IVec3 {
+        match self {
+            Face::PosX => IVec3::X,
+            Face::NegX => IVec3::NEG_X,
+            Face::PosY => IVec3::Y,
+            Face::NegY => IVec3::NEG_Y,
+            Face::PosZ => IVec3::Z,
+            Face::NegZ => IVec3::NEG_Z,
+        }
+    }
+
+    /// Index of the axis the face is perpendicular to (0 = x, 1 = y, 2 = z).
+    pub fn axis(self) -> usize {
+        match self {
+            Face::PosX | Face::NegX => 0,
+            Face::PosY | Face::NegY => 1,
+            Face::PosZ | Face::NegZ => 2,
+        }
+    }
+
+    pub fn is_positive(self) -> bool {
+        matches!(self, Face::PosX | Face::PosY | Face::PosZ)
+    }
+}
+
+/// A rectangle of merged, coplanar faces of the same block type, in chunk-local coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quad {
+    pub face: Face,
+    pub block_type: BlockType,
+    /// The block the quad starts at.
+    pub origin: IVec3,
+    /// Number of blocks covered along the face's `u` and `v` axes, see [`face_axes`].
+    pub size: UVec2,
+    /// Light of the blocks in front of the quad, only faces lit the same are merged.
+    pub light: Light,
+    /// Ambient occlusion of each corner, ordered like [`Quad::corners`], see [`corner_occlusion`].
+    pub occlusion: [u8; 4],
+}
+
+impl Quad {
+    /// Corners of the quad, wound counter-clockwise when looking at the face.
+    pub fn corners(&self) -> [Vec3; 4] {
+        let (u, v) = face_axes(self.face);
+
+        let mut base = self.origin.as_vec3();
+        if self.face.is_positive() {
+            base[self.face.axis()] += 1.0;
+        }
+
+        let mut du = Vec3::ZERO;
+        du[u] = self.size.x as f32;
+        let mut dv = Vec3::ZERO;
+        dv[v] = self.size.y as f32;
+
+        if self.face.is_positive() {
+            [base, base + du, base + du + dv, base + dv]
+        } else {
+            [base, base + dv, base + du + dv, base + du]
+        }
+    }
+}
+
+/// The two axes spanning a face, ordered so that `u x v` points along the positive face normal.
+pub fn face_axes(face: Face) -> (usize, usize) {
+    let axis = face.axis();
+
+    return ((axis + 1) % 3, (axis + 2) % 3);
+}
+
+/// Direction along the face's `u` and `v` axes each corner of [`Quad::corners`] lies in.
+fn corner_directions(face: Face) -> [IVec2; 4] {
+    if face.is_positive() {
+        [
+            IVec2::new(-1, -1),
+            IVec2::new(1, -1),
+            IVec2::new(1, 1),
+            IVec2::new(-1, 1),
+        ]
+    } else {
+        [
+            IVec2::new(-1, -1),
+            IVec2::new(-1, 1),
+            IVec2::new(1, 1),
+            IVec2::new(1, -1),
+        ]
+    }
+}
+
+/// Ambient occlusion of a face corner, from `0` (darkest) to `3` (unoccluded), given whether
+/// the two blocks in front of the face along the edges meeting at the corner and the block
+/// diagonally in front of it occlude. Two occluding sides hide the corner completely,
+/// whatever is in between.
+pub fn corner_occlusion(side1: bool, side2: bool, corner: bool) -> u8 {
+    if side1 && side2 {
+        return 0;
+    }
+
+    return 3 - (side1 as u8 + side2 as u8 + corner as u8);
+}
+
+/// Ambient occlusion of the corners of a face, ordered like [`Quad::corners`].
+/// `front` is the block the face looks at, `occludes` whether a block darkens the corners next to it.
+pub fn face_occlusion(face: Face, front: IVec3, occludes: impl Fn(IVec3) -> bool) -> [u8; 4] {
+    let (u, v) = face_axes(face);
+
+    return corner_directions(face).map(|direction| {
+        let mut along_u = IVec3::ZERO;
+        along_u[u] = direction.x;
+        let mut along_v = IVec3::ZERO;
+        along_v[v] = direction.y;
+
+        corner_occlusion(
+            occludes(front + along_u),
+            occludes(front + along_v),
+            occludes(front + along_u + along_v),
+        )
+    });
+}
+
+/// Builds the exposed faces of a chunk, merging neighbouring faces of the same block type
+/// into as few quads as possible.
+///
+/// A face is exposed if the block in front of it is air, or a transparent block of another type.
+/// Only faces with the same light and ambient occlusion are merged.
+/// `outside` is asked for blocks and their light beyond the chunk bounds (given in chunk-local
+/// coordinates), so faces against loaded neighbour chunks can be culled, lit and shaded as well.
+pub fn greedy_mesh(
+    chunk: &Chunk,
+    block_registry: &BlockRegistry,
+    outside: impl Fn(IVec3) -> (Option<BlockType>, Light),
+) -> Vec<Quad> {
+    let mut quads = Vec::new();
+
+    let voxel_at = |position: IVec3| -> (Option<BlockType>, Light) {
+        if position.cmplt(IVec3::ZERO).any() || position.cmpge(CHUNK_DIMENSIONS).any() {
+            outside(position)
+        } else {
+            let local = position.as_uvec3();
+            (chunk.get_block(local), chunk.get_light(local))
+        }
+    };
+
+    let occludes = |position: IVec3| {
+        let (block, _) = voxel_at(position);
+        block.is_some_and(|block_type| !block_registry.is_transparent(block_type))
+    };
+
+    for face in Face::ALL {
+        let axis = face.axis();
+        let (u, v) = face_axes(face);
+        let normal = face.normal();
+
+        let width = CHUNK_DIMENSIONS[u] as usize;
+        let height = CHUNK_DIMENSIONS[v] as usize;
+        let mut mask: Vec<Option<(BlockType, Light, [u8; 4])>> = vec![None; width * height];
+
+        for slice in 0..CHUNK_DIMENSIONS[axis] {
+            // collect the visible faces of this slice
+            for j in 0..height {
+                for i in 0..width {
+                    let mut position = IVec3::ZERO;
+                    position[axis] = slice;
+                    position[u] = i as i32;
+                    position[v] = j as i32;
+
+                    let (block, _) = voxel_at(position);
+                    let (neighbour, light) = voxel_at(position + normal);
+
+                    let visible = match (block, neighbour) {
+                        (Some(block_type), None) => Some(block_type),
+                        (Some(block_type), Some(neighbour))
+                            if neighbour != block_type
+                                && block_registry.is_transparent(neighbour) =>
+                        {
+                            Some(block_type)
+                        }
+                        _ => None,
+                    };
+
+                    mask[j * width + i] = visible.map(|block_type| {
+                        let occlusion = face_occlusion(face, position + normal, occludes);
+                        (block_type, light, occlusion)
+                    });
+                }
+            }
+
+            // and merge them into rectangles, first along u, then along v
+            for j in 0..height {
+                let mut i = 0;
+
+                while i < width {
+                    let Some(visible) = mask[j * width + i] else {
+                        i += 1;
+                        continue;
+                    };
+
+                    let mut quad_width = 1;
+                    while i + quad_width < width
+                        && mask[j * width + i + quad_width] == Some(visible)
+                    {
+                        quad_width += 1;
+                    }
+
+                    let mut quad_height = 1;
+                    'grow: while j + quad_height < height {
+                        for k in 0..quad_width {
+                            if mask[(j + quad_height) * width + i + k] != Some(visible) {
+                                break 'grow;
+                            }
+                        }
+
+                        quad_height += 1;
+                    }
+
+                    for row in 0..quad_height {
+                        for k in 0..quad_width {
+                            mask[(j + row) * width + i + k] = None;
+                        }
+                    }
+
+                    let mut origin = IVec3::ZERO;
+                    origin[axis] = slice;
+                    origin[u] = i as i32;
+                    origin[v] = j as i32;
+
+                    let (block_type, light, occlusion) = visible;
+
+                    quads.push(Quad {
+                        face,
+                        block_type,
+                        origin,
+                        size: UVec2::new(quad_width as u32, quad_height as u32),
+                        light,
+                        occlusion,
+                    });
+
+                    i += quad_width;
+                }
+            }
+        }
+    }
+
+    return quads;
+}
+
+/// Brightness of each face direction, so the sides of a block can be told apart even when lit evenly.
+fn face_shade(face: Face) -> f32 {
+    match face {
+        Face::PosY => 1.0,
+        Face::NegY => 0.5,
+        Face::PosX | Face::NegX => 0.8,
+        Face::PosZ | Face::NegZ => 0.7,
+    }
+}
+
+/// Brightness of a corner for each ambient occlusion value.
+const OCCLUSION_BRIGHTNESS: [f32; 4] = [0.45, 0.65, 0.82, 1.0];
+
+/// Order the corners of a quad are drawn in as two triangles. The quad is split along the
+/// diagonal whose corners are brighter in total, otherwise the occlusion of a single corner
+/// smears along the diagonal and the same corner looks different depending on its orientation.
+pub fn quad_triangles(occlusion: [u8; 4]) -> [u32; 6] {
+    if occlusion[0] + occlusion[2] >= occlusion[1] + occlusion[3] {
+        [0, 1, 2, 0, 2, 3]
+    } else {
+        [1, 2, 3, 1, 3, 0]
+    }
+}
+
+/// Texture coordinates of a corner in blocks, the atlas tile repeats once per unit.
+/// Side faces run `v` downwards so textures stay upright.
+fn corner_uv(face: Face, corner: Vec3) -> [f32; 2] {
+    match face {
+        Face::PosY | Face::NegY => [corner.x, corner.z],
+        Face::PosX | Face::NegX => [corner.z, -corner.y],
+        Face::PosZ | Face::NegZ => [corner.x, -corner.y],
+    }
+}
+
+/// Turns quads into a mesh drawn with [`ChunkMaterial`](crate::material::ChunkMaterial).
+/// Textured blocks sample their atlas tile, untextured ones use the white tile tinted by their colour.
+pub fn build_mesh(
+    quads: &[Quad],
+    block_registry: &BlockRegistry,
+    block_atlas: &BlockAtlas,
+) -> Mesh {
+    let mut positions: Vec<[f32; 3]> = Vec::with_capacity(quads.len() * 4);
+    let mut normals: Vec<[f32; 3]> = Vec::with_capacity(quads.len() * 4);
+    let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(quads.len() * 4);
+    let mut colors: Vec<[f32; 4]> = Vec::with_capacity(quads.len() * 4);
+    let mut tiles: Vec<[f32; 4]> = Vec::with_capacity(quads.len() * 4);
+    let mut indices: Vec<u32> = Vec::with_capacity(quads.len() * 6);
+
+    for quad in quads {
+        let start = positions.len() as u32;
+        let normal = quad.face.normal().as_vec3().to_array();
+
+        let tint = if block_atlas.is_untextured(quad.block_type) {
+            block_registry.get(quad.block_type).color()
+        } else {
+            Color::WHITE
+        };
+        let shade = face_shade(quad.face) * quad.light.brightness();
+        let [r, g, b, a] = tint.as_linear_rgba_f32();
+
+        let tile = block_atlas.tile_uv(quad.block_type, quad.face);
+        let tile = [tile.min.x, tile.min.y, tile.max.x, tile.max.y];
+
+        for (corner, occlusion) in quad.corners().into_iter().zip(quad.occlusion) {
+            let shade = shade * OCCLUSION_BRIGHTNESS[occlusion as usize];
+
+            positions.push(corner.to_array());
+            normals.push(normal);
+            uvs.push(corner_uv(quad.face, corner));
+            colors.push([r * shade, g * shade, b * shade, a]);
+            tiles.push(tile);
+        }
+
+        indices.extend(quad_triangles(quad.occlusion).map(|index| start + index));
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.insert_attribute(ATTRIBUTE_TILE_RECT, tiles);
+    mesh.set_indices(Some(Indices::U32(indices)));
+
+    return mesh;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mesh(blocks: &[UVec3]) -> Vec<Quad> {
+        let block_registry = BlockRegistry::from_assets();
+        let stone = block_registry.by_id("stone").unwrap();
+
+        let mut chunk = Chunk::empty(IVec3::ZERO);
+        for local in blocks {
+            chunk.set_block(*local, Some(stone));
+        }
+
+        return greedy_mesh(&chunk, &block_registry, |_| (None, Light::default()));
+    }
+
+    fn count(quads: &[Quad], face: Face) -> usize {
+        quads.iter().filter(|quad| quad.face == face).count()
+    }
+
+    fn find(quads: &[Quad], face: Face) -> Quad {
+        *quads.iter().find(|quad| quad.face == face).unwrap()
+    }
+
+    /// Checks that every quad is wound counter-clockwise when looking at its face.
+    fn assert_winding(quads: &[Quad]) {
+        for quad in quads {
+            let [a, b, c, _] = quad.corners();
+            let normal = (b - a).cross(c - a).normalize();
+
+            assert_eq!(normal, quad.face.normal().as_vec3(), "{:?}", quad);
+        }
+    }
+
+    #[test]
+    fn single_cube() {
+        let quads = mesh(&[UVec3::new(3, 4, 5)]);
+
+        assert_eq!(quads.len(), 6);
+        for face in Face::ALL {
+            let quad = find(&quads, face);
+            assert_eq!(quad.origin, IVec3::new(3, 4, 5));
+            assert_eq!(quad.size, UVec2::ONE);
+            assert_eq!(quad.occlusion, [3; 4]);
+        }
+        assert_winding(&quads);
+
+        assert_eq!(
+            find(&quads, Face::PosY).corners(),
+            [
+                Vec3::new(3.0, 5.0, 5.0),
+                Vec3::new(3.0, 5.0, 6.0),
+                Vec3::new(4.0, 5.0, 6.0),
+                Vec3::new(4.0, 5.0, 5.0),
+            ]
+        );
+        assert_eq!(
+            find(&quads, Face::NegX).corners(),
+            [
+                Vec3::new(3.0, 4.0, 5.0),
+                Vec3::new(3.0, 4.0, 6.0),
+                Vec3::new(3.0, 5.0, 6.0),
+                Vec3::new(3.0, 5.0, 5.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn slab() {
+        let mut blocks = Vec::new();
+        for x in 0..16 {
+            for z in 0..16 {
+                blocks.push(UVec3::new(x, 0, z));
+            }
+        }
+
+        let quads = mesh(&blocks);
+
+        // every face of the slab merges into a single quad
+        assert_eq!(quads.len(), 6);
+        assert_winding(&quads);
+
+        let top = find(&quads, Face::PosY);
+        assert_eq!(top.size, UVec2::new(16, 16));
+        assert_eq!(
+            top.corners(),
+            [
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 16.0),
+                Vec3::new(16.0, 1.0, 16.0),
+                Vec3::new(16.0, 1.0, 0.0),
+            ]
+        );
+
+        let side = find(&quads, Face::PosX);
+        assert_eq!(side.origin, IVec3::new(15, 0, 0));
+        assert_eq!(side.size, UVec2::new(1, 16));
+        assert_eq!(
+            side.corners(),
+            [
+                Vec3::new(16.0, 0.0, 0.0),
+                Vec3::new(16.0, 1.0, 0.0),
+                Vec3::new(16.0, 1.0, 16.0),
+                Vec3::new(16.0, 0.0, 16.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn staircase() {
+        // three steps rising along x, one block deep
+        let quads = mesh(&[
+            UVec3::new(0, 0, 0),
+            UVec3::new(1, 0, 0),
+            UVec3::new(1, 1, 0),
+            UVec3::new(2, 0, 0),
+            UVec3::new(2, 1, 0),
+            UVec3::new(2, 2, 0),
+        ]);
+
+        assert_winding(&quads);
+
+        // the treads and risers are at different heights, so none of them merge
+        assert_eq!(count(&quads, Face::PosY), 3);
+        assert_eq!(count(&quads, Face::NegX), 3);
+        // the bottom and the back are flat
+        assert_eq!(count(&quads, Face::NegY), 1);
+        assert_eq!(find(&quads, Face::NegY).size, UVec2::new(1, 3));
+        assert_eq!(count(&quads, Face::PosX), 1);
+        assert_eq!(find(&quads, Face::PosX).size, UVec2::new(3, 1));
+        // the sides merge row by row
+        for face in [Face::PosZ, Face::NegZ] {
+            let mut widths: Vec<u32> = quads
+                .iter()
+                .filter(|quad| quad.face == face)
+                .map(|quad| quad.size.x)
+                .collect();
+            widths.sort();
+
+            assert_eq!(widths, [1, 2, 3]);
+        }
+
+        let treads: Vec<f32> = quads
+            .iter()
+            .filter(|quad| quad.face == Face::PosY)
+            .map(|quad| quad.corners()[0].y)
+            .collect();
+        assert_eq!(treads, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn corner_occlusion_of_every_neighbourhood() {
+        let cases = [
+            ((false, false, false), 3),
+            ((false, false, true), 2),
+            ((false, true, false), 2),
+            ((true, false, false), 2),
+            ((false, true, true), 1),
+            ((true, false, true), 1),
+            // two sides hide the corner, whether the block between them is there or not
+            ((true, true, false), 0),
+            ((true, true, true), 0),
+        ];
+
+        for ((side1, side2, corner), occlusion) in cases {
+            assert_eq!(
+                corner_occlusion(side1, side2, corner),
+                occlusion,
+                "{:?}",
+                (side1, side2, corner)
+            );
+        }
+    }
+
+    #[test]
+    fn face_occlusion_of_every_neighbourhood() {
+        // the top face of a block at the origin, with blocks next to its first corner
+        let front = IVec3::Y;
+        let side1 = front + IVec3::NEG_Z;
+        let side2 = front + IVec3::NEG_X;
+        let corner = front + IVec3::new(-1, 0, -1);
+
+        // the sides are shared with the neighbouring corners, the diagonal block isn't
+        let cases = [
+            ((false, false, false), [3, 3, 3, 3]),
+            ((false, false, true), [2, 3, 3, 3]),
+            ((false, true, false), [2, 2, 3, 3]),
+            ((true, false, false), [2, 3, 3, 2]),
+            ((false, true, true), [1, 2, 3, 3]),
+            ((true, false, true), [1, 3, 3, 2]),
+            ((true, true, false), [0, 2, 3, 2]),
+            ((true, true, true), [0, 2, 3, 2]),
+        ];
+
+        for ((has_side1, has_side2, has_corner), occlusion) in cases {
+            let occluders: Vec<IVec3> =
+                [(has_side1, side1), (has_side2, side2), (has_corner, corner)]
+                    .into_iter()
+                    .filter(|(present, _)| *present)
+                    .map(|(_, position)| position)
+                    .collect();
+
+            assert_eq!(
+                face_occlusion(Face::PosY, front, |position| occluders.contains(&position)),
+                occlusion,
+                "{:?}",
+                occluders
+            );
+        }
+    }
+
+    #[test]
+    fn occlusion_where_a_step_meets_the_tread_below() {
+        let quads = mesh(&[
+            UVec3::new(0, 0, 0),
+            UVec3::new(1, 0, 0),
+            UVec3::new(1, 1, 0),
+        ]);
+
+        let tread = quads
+            .iter()
+            .find(|quad| quad.face == Face::PosY && quad.origin == IVec3::ZERO)
+            .unwrap();
+
+        // the corners against the step, at the far x side, are darkened
+        assert_eq!(tread.corners()[2].x, 1.0);
+        assert_eq!(tread.corners()[3].x, 1.0);
+        assert_eq!(tread.occlusion, [3, 3, 2, 2]);
+    }
+
+    #[test]
+    fn quad_triangles_split_along_the_brighter_diagonal() {
+        assert_eq!(quad_triangles([3, 3, 3, 3]), [0, 1, 2, 0, 2, 3]);
+        assert_eq!(quad_triangles([2, 2, 2, 2]), [0, 1, 2, 0, 2, 3]);
+        // the same occlusion rotated by a corner splits the other way
+        assert_eq!(quad_triangles([3, 0, 3, 3]), [0, 1, 2, 0, 2, 3]);
+        assert_eq!(quad_triangles([0, 3, 3, 3]), [1, 2, 3, 1, 3, 0]);
+        assert_eq!(quad_triangles([3, 3, 0, 3]), [1, 2, 3, 1, 3, 0]);
+        assert_eq!(quad_triangles([3, 3, 3, 1]), [0, 1, 2, 0, 2, 3]);
+        assert_eq!(quad_triangles([2, 3, 2, 3]), [1, 2, 3, 1, 3, 0]);
+
+        // both triangles still cover all four corners
+        for occlusion in [[3, 3, 3, 3], [0, 3, 3, 3]] {
+            let mut corners = quad_triangles(occlusion).to_vec();
+            corners.sort();
+            corners.dedup();
+            assert_eq!(corners, [0, 1, 2, 3]);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::sync::Arc;
+
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+    utils::HashMap,
+};
+use bevy_rapier3d::prelude::Collider;
+use futures_lite::future;
+
+use super::{
+    collider::build_collider,
+    light::Light,
+    mesh::{build_mesh, greedy_mesh, Quad},
+    Chunk, ChunkEntities, ChunkEntity, ChunkMaterials, ChunkRegistry, CHUNK_DIMENSIONS, CHUNK_SIZE,
+};
+use crate::{
+    block::{atlas::BlockAtlas, BlockRegistry, BlockType},
+    material::ChunkMaterial,
+};
+
+/// Copies of the block data meshing tasks need, shared with them instead of borrowing resources.
+#[derive(Resource, Clone)]
+pub struct MeshingContext {
+    pub block_registry: Arc<BlockRegistry>,
+    pub block_atlas: Arc<BlockAtlas>,
+}
+
+/// What a finished meshing task produced for a chunk, `None` if it has no visible faces.
+pub struct ChunkGeometry {
+    pub opaque: Option<Mesh>,
+    /// Faces of transparent blocks, drawn after everything else so what's behind them shows.
+    pub transparent: Option<Mesh>,
+    pub collider: Option<Collider>,
+}
+
+/// Chunks being meshed on the [`AsyncComputeTaskPool`], keyed by chunk coordinate.
+/// Dropping a task cancels it.
+#[derive(Resource, Default)]
+pub struct MeshingChunks(pub HashMap<IVec3, Task<Option<ChunkGeometry>>>);
+
+/// A chunk along with the blocks around it its faces depend on, copied so it can be meshed
+/// in the background while the originals keep changing.
+struct ChunkSnapshot {
+    chunk: Chunk,
+    /// Blocks and light of the one block thick shell around the chunk,
+    /// keyed by their position relative to the chunk.
+    shell: HashMap<IVec3, (Option<BlockType>, Light)>,
+}
+
+impl ChunkSnapshot {
+    fn new(chunk_registry: &ChunkRegistry, chunk: &Chunk) -> Self {
+        let mut shell = HashMap::new();
+        let size = CHUNK_SIZE as i32;
+
+        for x in -1..=size {
+            for y in -1..=size {
+                for z in -1..=size {
+                    let local = IVec3::new(x, y, z);
+
+                    if (local.cmpge(IVec3::ZERO) & local.cmplt(CHUNK_DIMENSIONS)).all() {
+                        continue;
+                    }
+
+                    let world = ChunkRegistry::chunk_to_world(chunk.position, local);
+                    shell.insert(local, Self::voxel(chunk_registry, world));
+                }
+            }
+        }
+
+        return Self {
+            chunk: chunk.clone(),
+            shell,
+        };
+    }
+
+    /// Block and light at a world space position. Unloaded chunks are empty
+    /// and lit by the sky, like they are for the light itself.
+    fn voxel(chunk_registry: &ChunkRegistry, position: IVec3) -> (Option<BlockType>, Light) {
+        return match chunk_registry.get_light(position) {
+            Some(light) => (chunk_registry.get_block(position), light),
+            None => (None, Light::SKY),
+        };
+    }
+
+    fn build(&self, context: &MeshingContext) -> Option<ChunkGeometry> {
+        let quads = greedy_mesh(&self.chunk, &context.block_registry, |local| {
+            self.shell[&local]
+        });
+
+        // an empty chunk has nothing to render or collide with
+        if quads.is_empty() {
+            return None;
+        }
+
+        let collider = build_collider(&quads, &context.block_registry);
+        let (transparent, opaque): (Vec<Quad>, Vec<Quad>) = quads
+            .into_iter()
+            .partition(|quad| context.block_registry.is_transparent(quad.block_type));
+
+        let layer = |quads: Vec<Quad>| {
+            if quads.is_empty() {
+                return None;
+            }
+
+            Some(build_mesh(
+                &quads,
+                &context.block_registry,
+                &context.block_atlas,
+            ))
+        };
+
+        return Some(ChunkGeometry {
+            opaque: layer(opaque),
+            transparent: layer(transparent),
+            collider,
+        });
+    }
+}
+
+/// Starts rebuilding the meshes and colliders of all chunks that changed since the last frame.
+/// A chunk that changes again before its task finished gets a new task, replacing the stale one.
+pub fn queue_dirty_chunks(
+    mut chunk_registry: ResMut<ChunkRegistry>,
+    mut meshing_chunks: ResMut<MeshingChunks>,
+    meshing_context: Res<MeshingContext>,
+) {
+    if chunk_registry.dirty.is_empty() {
+        return;
+    }
+
+    let task_pool = AsyncComputeTaskPool::get();
+    let dirty: Vec<IVec3> = chunk_registry.dirty.drain().collect();
+
+    for position in dirty {
+        let Some(chunk) = chunk_registry.get_chunk(position) else {
+            continue;
+        };
+
+        let snapshot = ChunkSnapshot::new(&chunk_registry, chunk);
+        let context = meshing_context.clone();
+        let task = task_pool.spawn(async move { snapshot.build(&context) });
+
+        meshing_chunks.0.insert(position, task);
+    }
+}
+
+/// Hands the meshes and colliders of finished meshing tasks to their chunk entities,
+/// spawning or despawning those as needed.
+pub fn apply_chunk_geometry(
+    mut commands: Commands,
+    mut chunk_registry: ResMut<ChunkRegistry>,
+    mut meshing_chunks: ResMut<MeshingChunks>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    chunk_materials: Res<ChunkMaterials>,
+) {
+    let mut finished = Vec::new();
+
+    meshing_chunks.0.retain(
+        |position, task| match future::block_on(future::poll_once(task)) {
+            Some(geometry) => {
+                finished.push((*position, geometry));
+                false
+            }
+            None => true,
+        },
+    );
+
+    for (position, geometry) in finished {
+        // the chunk was unloaded while it was being meshed
+        let Some(chunk) = chunk_registry.get_chunk_mut(position) else {
+            continue;
+        };
+
+        let Some(geometry) = geometry else {
+            if let Some(entities) = chunk.entities.take() {
+                commands.entity(entities.root).despawn_recursive();
+            }
+
+            continue;
+        };
+
+        let entities = match chunk.entities {
+            Some(entities) => entities,
+            None => {
+                let mut spawn_layer = |material: &Handle<ChunkMaterial>| {
+                    commands
+                        .spawn(MaterialMeshBundle {
+                            material: material.clone(),
+                            ..default()
+                        })
+                        .id()
+                };
+
+                let opaque = spawn_layer(&chunk_materials.opaque);
+                let transparent = spawn_layer(&chunk_materials.transparent);
+
+                let root = commands
+                    .spawn(SpatialBundle::from_transform(Transform::from_translation(
+                        chunk.world_origin().as_vec3(),
+                    )))
+                    .insert((ChunkEntity, Name::new(format!("Chunk {}", position))))
+                    .push_children(&[opaque, transparent])
+                    .id();
+
+                ChunkEntities {
+                    root,
+                    opaque,
+                    transparent,
+                }
+            }
+        };
+
+        // a layer without faces keeps its old mesh, hidden until it has faces again
+        for (entity, mesh) in [
+            (entities.opaque, geometry.opaque),
+            (entities.transparent, geometry.transparent),
+        ] {
+            match mesh {
+                Some(mesh) => commands
+                    .entity(entity)
+                    .insert((meshes.add(mesh), Visibility::Inherited)),
+                None => commands.entity(entity).insert(Visibility::Hidden),
+            };
+        }
+
+        match geometry.collider {
+            Some(collider) => commands.entity(entities.root).insert(collider),
+            None => commands.entity(entities.root).remove::<Collider>(),
+        };
+
+        chunk.entities = Some(entities);
+    }
+}
@@ -0,0 +1,389 @@
+use std::sync::Arc;
+
+use bevy::{
+    asset::FileAssetIo,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    block::{atlas::BlockAtlas, BlockRegistry, BlockType},
+    material::ChunkMaterial,
+    state::{world_exists, GameState},
+};
+
+use self::{
+    caves::{CaveSettings, CAVE_SETTINGS_PATH},
+    fluid::flow_fluids,
+    generation::{spawn_heightmap_overlay, TerrainGenerator, WorldSeed},
+    light::{light_chunks, Light},
+    meshing::{apply_chunk_geometry, queue_dirty_chunks, MeshingChunks, MeshingContext},
+    region::{
+        insert_loaded_regions, save_modified_chunks, AutosaveTimer, LoadingRegions, RegionStore,
+    },
+    streaming::{insert_generated_chunks, stream_chunks, ChunkStreaming, GeneratingChunks},
+    structure::{PendingWrites, StructureTemplate, STRUCTURE_TEMPLATES_PATH},
+};
+
+pub mod biome;
+pub mod caves;
+pub mod collider;
+pub mod fluid;
+pub mod generation;
+pub mod light;
+pub mod mesh;
+pub mod meshing;
+pub mod raycast;
+pub mod region;
+pub mod streaming;
+pub mod structure;
+
+/// Chunks are cubes of this many blocks per side, stacked in all three directions.
+pub const CHUNK_SIZE: usize = 16;
+const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+pub const CHUNK_DIMENSIONS: IVec3 = IVec3::splat(CHUNK_SIZE as i32);
+
+pub struct ChunkPlugin;
+
+impl Plugin for ChunkPlugin {
+    fn build(&self, app: &mut App) {
+        let asset_folder = FileAssetIo::get_base_path().join("assets");
+
+        let seed = *app.world.get_resource_or_insert_with(WorldSeed::default);
+        let cave_settings = *app.world.get_resource_or_insert_with(|| {
+            CaveSettings::load(&asset_folder.join(CAVE_SETTINGS_PATH))
+                .unwrap_or_else(|error| panic!("Could not load the cave settings. {}", error))
+        });
+        let block_registry = app.world.resource::<BlockRegistry>();
+        let block_atlas = app.world.resource::<BlockAtlas>();
+        let structures =
+            StructureTemplate::load(&asset_folder.join(STRUCTURE_TEMPLATES_PATH), block_registry)
+                .unwrap_or_else(|error| {
+                    panic!("Could not load the structure templates. {}", error)
+                });
+        let terrain_generator =
+            TerrainGenerator::new(seed, cave_settings, structures, block_registry);
+        let mut region_store = RegionStore::default();
+        let pending_writes = region_store
+            .load_pending_writes(block_registry)
+            .unwrap_or_else(|error| {
+                println!("Could not load the pending structure blocks: {}", error);
+                PendingWrites::default()
+            });
+        let meshing_context = MeshingContext {
+            block_registry: Arc::new(block_registry.clone()),
+            block_atlas: Arc::new(block_atlas.clone()),
+        };
+
+        let atlas = block_atlas.image.clone();
+        let mut chunk_material_assets = app
+            .world
+            .get_resource_mut::<Assets<ChunkMaterial>>()
+            .unwrap();
+        let opaque = chunk_material_assets.add(ChunkMaterial {
+            atlas: atlas.clone(),
+            alpha_mode: AlphaMode::Opaque,
+        });
+        let transparent = chunk_material_assets.add(ChunkMaterial {
+            atlas,
+            alpha_mode: AlphaMode::Blend,
+        });
+
+        app.add_system(spawn_heightmap_overlay.in_schedule(OnEnter(GameState::Loading)))
+            .add_systems(
+                (
+                    insert_loaded_regions,
+                    stream_chunks,
+                    insert_generated_chunks,
+                    light_chunks,
+                    queue_dirty_chunks,
+                    apply_chunk_geometry,
+                )
+                    .chain()
+                    .distributive_run_if(world_exists),
+            )
+            .add_system(
+                flow_fluids
+                    .in_schedule(CoreSchedule::FixedUpdate)
+                    .run_if(in_state(GameState::InGame)),
+            )
+            .add_system(save_modified_chunks.in_base_set(CoreSet::Last))
+            .insert_resource(ChunkMaterials {
+                opaque,
+                transparent,
+            })
+            .insert_resource(ChunkRegistry::default())
+            .insert_resource(region_store)
+            .insert_resource(LoadingRegions::default())
+            .insert_resource(AutosaveTimer::default())
+            .insert_resource(terrain_generator)
+            .insert_resource(meshing_context)
+            .insert_resource(ChunkStreaming::default())
+            .insert_resource(GeneratingChunks::default())
+            .insert_resource(pending_writes)
+            .insert_resource(MeshingChunks::default());
+    }
+}
+
+/// Materials shared by all chunk meshes, textures come from the block atlas.
+#[derive(Resource)]
+pub struct ChunkMaterials {
+    pub opaque: Handle<ChunkMaterial>,
+    /// Blended, for blocks like water that let the blocks behind them shine through.
+    pub transparent: Handle<ChunkMaterial>,
+}
+
+/// Marks the root entities rendering chunks, see [`ChunkEntities`].
+#[derive(Component, Debug)]
+pub struct ChunkEntity;
+
+/// Entities rendering a chunk. Opaque and transparent blocks need different materials,
+/// so each gets a child of the root, which carries the collider.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkEntities {
+    pub root: Entity,
+    pub opaque: Entity,
+    pub transparent: Entity,
+}
+
+/// Owns the voxel data of every loaded chunk, keyed by chunk coordinate.
+#[derive(Resource, Default)]
+pub struct ChunkRegistry {
+    pub chunks: HashMap<IVec3, Chunk>,
+    /// Chunks whose voxels changed since their mesh was last built.
+    pub dirty: HashSet<IVec3>,
+    /// Chunks whose voxels changed since they were last saved. Freshly generated chunks
+    /// are not included, they can be generated again.
+    pub modified: HashSet<IVec3>,
+    /// Blocks next to a change, which the fluid simulation looks at on its next step.
+    pub fluid_updates: HashSet<IVec3>,
+    /// Chunks added since the light was last updated, see [`light::update_light`].
+    pub unlit: HashSet<IVec3>,
+    /// Blocks that changed since the light was last updated.
+    pub light_updates: HashSet<IVec3>,
+}
+
+impl ChunkRegistry {
+    /// Splits a world space block position into the coordinate of the chunk containing it
+    /// and the block's local position inside that chunk.
+    pub fn world_to_chunk(position: IVec3) -> (IVec3, UVec3) {
+        let chunk = IVec3::new(
+            position.x.div_euclid(CHUNK_DIMENSIONS.x),
+            position.y.div_euclid(CHUNK_DIMENSIONS.y),
+            position.z.div_euclid(CHUNK_DIMENSIONS.z),
+        );
+        let local = position - chunk * CHUNK_DIMENSIONS;
+
+        return (chunk, local.as_uvec3());
+    }
+
+    /// Inverse of [`ChunkRegistry::world_to_chunk`]. The local position may lie outside
+    /// the chunk bounds, which is handy when looking at neighbouring blocks.
+    pub fn chunk_to_world(chunk: IVec3, local: IVec3) -> IVec3 {
+        return chunk * CHUNK_DIMENSIONS + local;
+    }
+
+    /// Adds a chunk, scheduling it and its neighbours for a rebuild so the faces
+    /// between them get culled and shaded, and the chunk to be lit.
+    pub fn insert(&mut self, chunk: Chunk) {
+        let position = chunk.position;
+        self.chunks.insert(position, chunk);
+        self.unlit.insert(position);
+
+        self.dirty.insert(position);
+        for neighbour in surrounding_chunks(position) {
+            if self.chunks.contains_key(&neighbour) {
+                self.dirty.insert(neighbour);
+            }
+        }
+    }
+
+    /// Drops a chunk, scheduling its neighbours for a rebuild as their border faces are exposed now.
+    pub fn remove(&mut self, position: IVec3) -> Option<Chunk> {
+        let chunk = self.chunks.remove(&position)?;
+
+        self.dirty.remove(&position);
+        self.unlit.remove(&position);
+        for neighbour in surrounding_chunks(position) {
+            if self.chunks.contains_key(&neighbour) {
+                self.dirty.insert(neighbour);
+            }
+        }
+
+        return Some(chunk);
+    }
+
+    pub fn get_chunk(&self, chunk: IVec3) -> Option<&Chunk> {
+        self.chunks.get(&chunk)
+    }
+
+    pub fn get_chunk_mut(&mut self, chunk: IVec3) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&chunk)
+    }
+
+    /// Returns the block at a world space position, or `None` for air and unloaded chunks.
+    pub fn get_block(&self, position: IVec3) -> Option<BlockType> {
+        let (chunk, local) = Self::world_to_chunk(position);
+
+        return self.chunks.get(&chunk)?.get_block(local);
+    }
+
+    /// Returns the light at a world space position, or `None` for unloaded chunks.
+    pub fn get_light(&self, position: IVec3) -> Option<Light> {
+        let (chunk, local) = Self::world_to_chunk(position);
+
+        return Some(self.chunks.get(&chunk)?.get_light(local));
+    }
+
+    /// Writes a block (or air, if `None`) at a world space position.
+    /// Returns `false` if the chunk containing the position is not loaded.
+    pub fn set_block(&mut self, position: IVec3, block: Option<BlockType>) -> bool {
+        let (chunk, local) = Self::world_to_chunk(position);
+
+        match self.chunks.get_mut(&chunk) {
+            Some(target) => {
+                target.set_block(local, block);
+                self.mark_dirty(chunk, local);
+                self.modified.insert(chunk);
+                self.light_updates.insert(position);
+
+                self.fluid_updates.insert(position);
+                for neighbour in NEIGHBOUR_OFFSETS {
+                    self.fluid_updates.insert(position + neighbour);
+                }
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Writes a fluid block at a world space position, `level` being how much of it there is,
+    /// up to [`SOURCE_LEVEL`](fluid::SOURCE_LEVEL).
+    /// Returns `false` if the chunk containing the position is not loaded.
+    pub fn set_fluid(&mut self, position: IVec3, block: BlockType, level: u8) -> bool {
+        if !self.set_block(position, Some(block)) {
+            return false;
+        }
+
+        let (chunk, local) = Self::world_to_chunk(position);
+        if let Some(target) = self.chunks.get_mut(&chunk) {
+            target.set_fluid_level(local, level);
+        }
+
+        return true;
+    }
+
+    /// Schedules a chunk for a rebuild, along with the neighbours touching the given block
+    /// on a face, edge or corner, as their faces are culled and shaded by it too.
+    fn mark_dirty(&mut self, chunk: IVec3, local: UVec3) {
+        self.dirty.insert(chunk);
+
+        let world = Self::chunk_to_world(chunk, local.as_ivec3());
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let (neighbour, _) = Self::world_to_chunk(world + IVec3::new(x, y, z));
+
+                    if neighbour != chunk && self.chunks.contains_key(&neighbour) {
+                        self.dirty.insert(neighbour);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The 26 chunks around a chunk, sharing a face, edge or corner with it.
+fn surrounding_chunks(position: IVec3) -> impl Iterator<Item = IVec3> {
+    (-1..=1)
+        .flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| IVec3::new(x, y, z))))
+        .filter(|offset| *offset != IVec3::ZERO)
+        .map(move |offset| position + offset)
+}
+
+const NEIGHBOUR_OFFSETS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+#[derive(Clone)]
+pub struct Chunk {
+    /// Dense voxel storage, `None` being air. Use [`Chunk::index`] to address it.
+    pub blocks: Vec<Option<BlockType>>,
+    /// Light of every block, addressed like `blocks`. Not saved, chunks are lit again when loaded.
+    pub light: Vec<Light>,
+    /// Levels of fluid blocks that are not sources, keyed by [`Chunk::index`].
+    pub fluid_levels: HashMap<usize, u8>,
+    /// Chunks whose structures reaching into this chunk were placed in it already,
+    /// see [`PendingWrites`]. Saved, so broken structure blocks don't grow back.
+    pub structure_sources: HashSet<IVec3>,
+    pub position: IVec3,
+    /// The entities rendering this chunk, once it has been meshed.
+    pub entities: Option<ChunkEntities>,
+}
+
+impl Chunk {
+    pub fn empty(position: IVec3) -> Self {
+        return Self {
+            blocks: vec![None; CHUNK_VOLUME],
+            light: vec![Light::default(); CHUNK_VOLUME],
+            fluid_levels: HashMap::new(),
+            structure_sources: HashSet::new(),
+            position,
+            entities: None,
+        };
+    }
+
+    pub fn index(local: UVec3) -> usize {
+        return local.x as usize * CHUNK_SIZE * CHUNK_SIZE
+            + local.y as usize * CHUNK_SIZE
+            + local.z as usize;
+    }
+
+    pub fn get_block(&self, local: UVec3) -> Option<BlockType> {
+        *self.blocks.get(Self::index(local))?
+    }
+
+    pub fn set_block(&mut self, local: UVec3, block: Option<BlockType>) {
+        let index = Self::index(local);
+        self.blocks[index] = block;
+        self.fluid_levels.remove(&index);
+    }
+
+    pub fn get_light(&self, local: UVec3) -> Light {
+        self.light[Self::index(local)]
+    }
+
+    pub fn set_light(&mut self, local: UVec3, light: Light) {
+        let index = Self::index(local);
+        self.light[index] = light;
+    }
+
+    /// Level of the fluid at a local position, meaningless if the block there is not a fluid.
+    pub fn fluid_level(&self, local: UVec3) -> u8 {
+        self.fluid_levels
+            .get(&Self::index(local))
+            .copied()
+            .unwrap_or(fluid::SOURCE_LEVEL)
+    }
+
+    pub fn set_fluid_level(&mut self, local: UVec3, level: u8) {
+        let index = Self::index(local);
+
+        if level >= fluid::SOURCE_LEVEL {
+            self.fluid_levels.remove(&index);
+        } else {
+            self.fluid_levels.insert(index, level);
+        }
+    }
+
+    /// World space position of the chunk's origin block.
+    pub fn world_origin(&self) -> IVec3 {
+        return ChunkRegistry::chunk_to_world(self.position, IVec3::ZERO);
+    }
+}
@@ -0,0 +1,207 @@
+use bevy::prelude::*;
+
+use super::ChunkRegistry;
+use crate::block::BlockRegistry;
+
+/// A block hit by [`raycast`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoxelHit {
+    /// World space position of the solid block that was hit.
+    pub block: IVec3,
+    /// Normal of the face the ray entered the block through.
+    pub normal: IVec3,
+    /// The empty cell in front of the hit face, i.e. where a block would be placed.
+    pub adjacent: IVec3,
+    /// Distance travelled along the ray until it entered the block.
+    pub distance: f32,
+}
+
+/// Walks the voxel grid cell by cell along a ray (Amanatides & Woo) and returns the first
+/// solid block within `max_distance`. The cell the ray starts in is never reported,
+/// so standing inside a block does not hide everything behind it.
+pub fn raycast(
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    is_solid: impl Fn(IVec3) -> bool,
+) -> Option<VoxelHit> {
+    let direction = direction.normalize_or_zero();
+
+    if direction == Vec3::ZERO || !max_distance.is_finite() {
+        return None;
+    }
+
+    let mut cell = origin.floor().as_ivec3();
+    let mut step = IVec3::ZERO;
+    // distance along the ray to the next cell boundary, per axis
+    let mut next_boundary = Vec3::splat(f32::INFINITY);
+    // distance along the ray between two boundaries, per axis
+    let mut boundary_spacing = Vec3::splat(f32::INFINITY);
+
+    for axis in 0..3 {
+        if direction[axis] > 0.0 {
+            step[axis] = 1;
+            next_boundary[axis] = (cell[axis] as f32 + 1.0 - origin[axis]) / direction[axis];
+            boundary_spacing[axis] = 1.0 / direction[axis];
+        } else if direction[axis] < 0.0 {
+            step[axis] = -1;
+            next_boundary[axis] = (origin[axis] - cell[axis] as f32) / -direction[axis];
+            boundary_spacing[axis] = 1.0 / -direction[axis];
+        }
+    }
+
+    loop {
+        let axis = if next_boundary.x < next_boundary.y {
+            if next_boundary.x < next_boundary.z {
+                0
+            } else {
+                2
+            }
+        } else if next_boundary.y < next_boundary.z {
+            1
+        } else {
+            2
+        };
+
+        let distance = next_boundary[axis];
+
+        if distance > max_distance {
+            return None;
+        }
+
+        cell[axis] += step[axis];
+        next_boundary[axis] += boundary_spacing[axis];
+
+        if is_solid(cell) {
+            let mut normal = IVec3::ZERO;
+            normal[axis] = -step[axis];
+
+            return Some(VoxelHit {
+                block: cell,
+                normal,
+                adjacent: cell + normal,
+                distance,
+            });
+        }
+    }
+}
+
+impl ChunkRegistry {
+    /// Casts a ray against the loaded solid blocks, see [`raycast`].
+    pub fn raycast(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        block_registry: &BlockRegistry,
+    ) -> Option<VoxelHit> {
+        raycast(origin, direction, max_distance, |position| {
+            self.get_block(position)
+                .is_some_and(|block_type| block_registry.is_solid(block_type))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CENTER: Vec3 = Vec3::splat(0.5);
+
+    #[test]
+    fn hits_along_each_axis() {
+        for normal in [
+            IVec3::X,
+            IVec3::NEG_X,
+            IVec3::Y,
+            IVec3::NEG_Y,
+            IVec3::Z,
+            IVec3::NEG_Z,
+        ] {
+            let target = normal * 4;
+            let hit = raycast(CENTER, normal.as_vec3(), 10.0, |cell| cell == target).unwrap();
+
+            assert_eq!(hit.block, target);
+            assert_eq!(hit.normal, -normal);
+            assert_eq!(hit.adjacent, normal * 3);
+            assert!((hit.distance - 3.5).abs() < 1e-5, "{:?}", hit);
+        }
+    }
+
+    #[test]
+    fn hits_the_first_solid_block() {
+        let hit = raycast(CENTER, Vec3::X, 10.0, |cell| cell.x >= 2).unwrap();
+
+        assert_eq!(hit.block, IVec3::new(2, 0, 0));
+    }
+
+    #[test]
+    fn ignores_the_starting_cell() {
+        let hit = raycast(CENTER, Vec3::X, 10.0, |cell| cell.x <= 0 || cell.x == 3).unwrap();
+
+        assert_eq!(hit.block, IVec3::new(3, 0, 0));
+    }
+
+    #[test]
+    fn misses_out_of_reach() {
+        let solid = |cell: IVec3| cell == IVec3::new(5, 0, 0);
+
+        assert!(raycast(CENTER, Vec3::X, 4.4, solid).is_none());
+        assert!(raycast(CENTER, Vec3::X, 4.5, solid).is_some());
+        assert!(raycast(CENTER, Vec3::NEG_X, 100.0, solid).is_none());
+        assert!(raycast(CENTER, Vec3::ZERO, 100.0, solid).is_none());
+    }
+
+    #[test]
+    fn grazing_a_corner() {
+        // passes exactly through the edge shared by four cells, and enters the diagonal one
+        let target = IVec3::new(1, 1, 0);
+        let hit = raycast(CENTER, Vec3::new(1.0, 1.0, 0.0), 10.0, |cell| {
+            cell == target
+        })
+        .unwrap();
+
+        assert_eq!(hit.block, target);
+        assert!((hit.distance - 0.5_f32.sqrt()).abs() < 1e-5, "{:?}", hit);
+        // the adjacent cell shares a face with the hit block, never just an edge
+        assert_eq!(hit.normal.abs().to_array().iter().sum::<i32>(), 1);
+        assert_eq!(hit.adjacent, hit.block + hit.normal);
+
+        // and through a corner shared by eight cells
+        let target = IVec3::new(1, 1, 1);
+        let hit = raycast(CENTER, Vec3::ONE, 10.0, |cell| cell == target).unwrap();
+
+        assert_eq!(hit.block, target);
+        assert_eq!(hit.normal.abs().to_array().iter().sum::<i32>(), 1);
+        assert_eq!(hit.adjacent, hit.block + hit.normal);
+    }
+
+    #[test]
+    fn adjacent_cell_is_in_front_of_the_hit_face() {
+        // looking down onto a floor at y = -3 from an angle, across negative coordinates
+        let hit = raycast(
+            Vec3::new(-0.2, 1.3, -7.9),
+            Vec3::new(0.3, -1.0, 0.2),
+            10.0,
+            |cell| cell.y == -3,
+        )
+        .unwrap();
+
+        assert_eq!(hit.block.y, -3);
+        assert_eq!(hit.normal, IVec3::Y);
+        assert_eq!(hit.adjacent, hit.block + IVec3::Y);
+
+        // hitting the side of a wall
+        let hit = raycast(
+            Vec3::new(0.5, 0.5, 0.5),
+            Vec3::new(-1.0, 0.1, 0.0),
+            10.0,
+            |cell| cell.x == -2,
+        )
+        .unwrap();
+
+        assert_eq!(hit.block, IVec3::new(-2, 0, 0));
+        assert_eq!(hit.normal, IVec3::X);
+        assert_eq!(hit.adjacent, IVec3::new(-1, 0, 0));
+    }
+}
@@ -0,0 +1,1072 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+    utils::{HashMap, HashSet},
+};
+use futures_lite::future;
+
+use super::{structure::PendingWrites, Chunk, ChunkRegistry, CHUNK_SIZE, CHUNK_VOLUME};
+use crate::block::{BlockRegistry, BlockType};
+
+/// Where the world is saved, relative to the working directory.
+const SAVE_DIRECTORY: &str = "saves/world";
+/// Regions are cubes of this many chunks per side.
+pub const REGION_SIZE: i32 = 16;
+/// Identifies region files, followed by the format version.
+const REGION_MAGIC: &[u8; 4] = b"VXRG";
+const REGION_VERSION: u8 = 4;
+/// Identifies the file of structure blocks waiting for their chunk, followed by the format version.
+const PENDING_MAGIC: &[u8; 4] = b"VXPW";
+const PENDING_VERSION: u8 = 1;
+/// How often modified chunks are written to disk, besides when the game is closed.
+const AUTOSAVE_INTERVAL_SECS: f32 = 30.0;
+
+/// Encoded chunks of one region file, keyed by their chunk coordinate inside the region.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Region {
+    pub chunks: HashMap<UVec3, Vec<u8>>,
+}
+
+impl Region {
+    /// Layout: magic, version, chunk count (u32), then per chunk its local coordinate (3 × u8),
+    /// length of the encoded chunk (u32) and the encoded chunk itself. Little endian throughout.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(REGION_MAGIC);
+        bytes.push(REGION_VERSION);
+        bytes.extend_from_slice(&(self.chunks.len() as u32).to_le_bytes());
+
+        for (local, data) in self.chunks.iter() {
+            bytes.extend_from_slice(&[local.x as u8, local.y as u8, local.z as u8]);
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(data);
+        }
+
+        return bytes;
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = ByteReader::new(bytes);
+
+        if reader.take(REGION_MAGIC.len())? != REGION_MAGIC {
+            return Err(invalid_data("not a region file"));
+        }
+
+        let version = reader.u8()?;
+        if version != REGION_VERSION {
+            return Err(invalid_data(&format!(
+                "unsupported region version {}",
+                version
+            )));
+        }
+
+        let count = reader.u32()?;
+        let mut chunks = HashMap::new();
+
+        for _ in 0..count {
+            let local = UVec3::new(
+                reader.u8()? as u32,
+                reader.u8()? as u32,
+                reader.u8()? as u32,
+            );
+            let length = reader.u32()? as usize;
+            chunks.insert(local, reader.take(length)?.to_vec());
+        }
+
+        return Ok(Self { chunks });
+    }
+}
+
+/// Reads a region file, a missing file being an empty region.
+fn read_region(path: &Path) -> io::Result<Region> {
+    return match fs::read(path) {
+        Ok(bytes) => Region::from_bytes(&bytes),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(Region::default()),
+        Err(error) => Err(error),
+    };
+}
+
+/// Splits a chunk coordinate into the region containing it and its position inside the region.
+pub fn chunk_to_region(chunk: IVec3) -> (IVec3, UVec3) {
+    let region = IVec3::new(
+        chunk.x.div_euclid(REGION_SIZE),
+        chunk.y.div_euclid(REGION_SIZE),
+        chunk.z.div_euclid(REGION_SIZE),
+    );
+    let local = chunk - region * REGION_SIZE;
+
+    return (region, local.as_uvec3());
+}
+
+/// Serializes the voxels of a chunk as a palette of block ids followed by runs of palette indices.
+/// Blocks are stored by their stable id, so saves survive reordering the block definitions.
+///
+/// Layout: palette length (u16), per entry the id length (u8) and id bytes, air being the empty id,
+/// then the run count (u32) and per run its palette index (u16) and length (u32),
+/// then the count of flowing fluid blocks (u32) and per block its index (u16) and level (u8),
+/// then the count of structure sources (u32) and per source its chunk coordinate (3 × i32).
+pub fn encode_chunk(chunk: &Chunk, block_registry: &BlockRegistry) -> Vec<u8> {
+    let mut palette: Vec<Option<BlockType>> = Vec::new();
+    let mut runs: Vec<(u16, u32)> = Vec::new();
+
+    for block in chunk.blocks.iter() {
+        let index = match palette.iter().position(|entry| entry == block) {
+            Some(index) => index,
+            None => {
+                palette.push(*block);
+                palette.len() - 1
+            }
+        } as u16;
+
+        match runs.last_mut() {
+            Some((last, length)) if *last == index => *length += 1,
+            _ => runs.push((index, 1)),
+        }
+    }
+
+    let mut bytes = Vec::new();
+
+    bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+    for entry in palette {
+        let id = entry.map_or("", |block_type| block_registry.get(block_type).id.as_str());
+        bytes.push(id.len() as u8);
+        bytes.extend_from_slice(id.as_bytes());
+    }
+
+    bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+    for (index, length) in runs {
+        bytes.extend_from_slice(&index.to_le_bytes());
+        bytes.extend_from_slice(&length.to_le_bytes());
+    }
+
+    // sorted, so an unchanged chunk always encodes the same
+    let mut fluid_levels: Vec<(usize, u8)> = chunk
+        .fluid_levels
+        .iter()
+        .map(|(index, level)| (*index, *level))
+        .collect();
+    fluid_levels.sort();
+
+    bytes.extend_from_slice(&(fluid_levels.len() as u32).to_le_bytes());
+    for (index, level) in fluid_levels {
+        bytes.extend_from_slice(&(index as u16).to_le_bytes());
+        bytes.push(level);
+    }
+
+    let mut structure_sources: Vec<IVec3> = chunk.structure_sources.iter().copied().collect();
+    structure_sources.sort_by_key(|source| source.to_array());
+
+    bytes.extend_from_slice(&(structure_sources.len() as u32).to_le_bytes());
+    for source in structure_sources {
+        for coordinate in source.to_array() {
+            bytes.extend_from_slice(&coordinate.to_le_bytes());
+        }
+    }
+
+    return bytes;
+}
+
+/// Inverse of [`encode_chunk`]. Fails on unknown block ids, if the runs don't fill the chunk
+/// or if a fluid level lies outside of it.
+pub fn decode_chunk(
+    bytes: &[u8],
+    position: IVec3,
+    block_registry: &BlockRegistry,
+) -> io::Result<Chunk> {
+    let mut reader = ByteReader::new(bytes);
+
+    let palette_length = reader.u16()?;
+    let mut palette = Vec::with_capacity(palette_length as usize);
+
+    for _ in 0..palette_length {
+        let length = reader.u8()? as usize;
+        let id = std::str::from_utf8(reader.take(length)?)
+            .map_err(|_| invalid_data("block id is not valid UTF-8"))?;
+
+        if id.is_empty() {
+            palette.push(None);
+            continue;
+        }
+
+        match block_registry.by_id(id) {
+            Some(block_type) => palette.push(Some(block_type)),
+            None => return Err(invalid_data(&format!("unknown block {:?}", id))),
+        }
+    }
+
+    let run_count = reader.u32()?;
+    let mut chunk = Chunk::empty(position);
+    let mut filled = 0;
+
+    for _ in 0..run_count {
+        let index = reader.u16()? as usize;
+        let length = reader.u32()? as usize;
+
+        let Some(block) = palette.get(index) else {
+            return Err(invalid_data("palette index out of range"));
+        };
+
+        if filled + length > CHUNK_VOLUME {
+            return Err(invalid_data("runs overflow the chunk"));
+        }
+
+        chunk.blocks[filled..filled + length].fill(*block);
+        filled += length;
+    }
+
+    if filled != CHUNK_VOLUME {
+        return Err(invalid_data("runs don't fill the chunk"));
+    }
+
+    let fluid_count = reader.u32()?;
+
+    for _ in 0..fluid_count {
+        let index = reader.u16()? as usize;
+        let level = reader.u8()?;
+
+        if index >= CHUNK_VOLUME {
+            return Err(invalid_data("fluid level outside of the chunk"));
+        }
+
+        chunk.fluid_levels.insert(index, level);
+    }
+
+    let source_count = reader.u32()?;
+
+    for _ in 0..source_count {
+        let source = IVec3::new(reader.i32()?, reader.i32()?, reader.i32()?);
+        chunk.structure_sources.insert(source);
+    }
+
+    return Ok(chunk);
+}
+
+/// Serializes the structure blocks waiting for chunks that were not generated yet,
+/// so a saved chunk's structures are still completed after a restart.
+///
+/// Layout: magic, version, target count (u32), per target chunk its coordinate (3 × i32)
+/// and source count (u32), per source chunk its coordinate (3 × i32) and block count (u32),
+/// per block its local coordinate (3 × u8), id length (u8) and id bytes.
+pub fn encode_pending_writes(
+    pending_writes: &PendingWrites,
+    block_registry: &BlockRegistry,
+) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    bytes.extend_from_slice(PENDING_MAGIC);
+    bytes.push(PENDING_VERSION);
+    bytes.extend_from_slice(&(pending_writes.0.len() as u32).to_le_bytes());
+
+    for (target, sources) in pending_writes.0.iter() {
+        for coordinate in target.to_array() {
+            bytes.extend_from_slice(&coordinate.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(sources.len() as u32).to_le_bytes());
+
+        for (source, writes) in sources.iter() {
+            for coordinate in source.to_array() {
+                bytes.extend_from_slice(&coordinate.to_le_bytes());
+            }
+            bytes.extend_from_slice(&(writes.len() as u32).to_le_bytes());
+
+            for (local, block_type) in writes {
+                let id = &block_registry.get(*block_type).id;
+
+                bytes.extend_from_slice(&[local.x as u8, local.y as u8, local.z as u8]);
+                bytes.push(id.len() as u8);
+                bytes.extend_from_slice(id.as_bytes());
+            }
+        }
+    }
+
+    return bytes;
+}
+
+/// Inverse of [`encode_pending_writes`]. Fails on unknown block ids.
+pub fn decode_pending_writes(
+    bytes: &[u8],
+    block_registry: &BlockRegistry,
+) -> io::Result<PendingWrites> {
+    let mut reader = ByteReader::new(bytes);
+
+    if reader.take(PENDING_MAGIC.len())? != PENDING_MAGIC {
+        return Err(invalid_data("not a pending structure blocks file"));
+    }
+
+    let version = reader.u8()?;
+    if version != PENDING_VERSION {
+        return Err(invalid_data(&format!(
+            "unsupported pending structure blocks version {}",
+            version
+        )));
+    }
+
+    let mut pending_writes = PendingWrites::default();
+    let target_count = reader.u32()?;
+
+    for _ in 0..target_count {
+        let target = IVec3::new(reader.i32()?, reader.i32()?, reader.i32()?);
+        let sources = pending_writes.0.entry(target).or_default();
+        let source_count = reader.u32()?;
+
+        for _ in 0..source_count {
+            let source = IVec3::new(reader.i32()?, reader.i32()?, reader.i32()?);
+            let write_count = reader.u32()?;
+            let mut writes = Vec::with_capacity(write_count as usize);
+
+            for _ in 0..write_count {
+                let local = UVec3::new(
+                    reader.u8()? as u32,
+                    reader.u8()? as u32,
+                    reader.u8()? as u32,
+                );
+                let length = reader.u8()? as usize;
+                let id = std::str::from_utf8(reader.take(length)?)
+                    .map_err(|_| invalid_data("block id is not valid UTF-8"))?;
+
+                if local.max_element() >= CHUNK_SIZE as u32 {
+                    return Err(invalid_data("structure block outside of its chunk"));
+                }
+
+                match block_registry.by_id(id) {
+                    Some(block_type) => writes.push((local, block_type)),
+                    None => return Err(invalid_data(&format!("unknown block {:?}", id))),
+                }
+            }
+
+            sources.insert(source, writes);
+        }
+    }
+
+    return Ok(pending_writes);
+}
+
+/// Writes next to the old file first, so a crash mid-write can't corrupt it.
+fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let temporary = path.with_extension("tmp");
+
+    fs::write(&temporary, bytes)?;
+    fs::rename(&temporary, path)?;
+
+    return Ok(());
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads little endian values off the front of a byte slice.
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < length {
+            return Err(io::Error::from(ErrorKind::UnexpectedEof));
+        }
+
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+
+        return Ok(taken);
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+/// A region file that could not be written, and the chunks that went unsaved with it.
+#[derive(Debug)]
+pub struct RegionError {
+    pub region: IVec3,
+    pub chunks: Vec<IVec3>,
+    pub error: io::Error,
+}
+
+/// Reads and writes chunks in region files, keeping every region it touched in memory
+/// so loading neighbouring chunks doesn't hit the disk again.
+#[derive(Resource, Debug)]
+pub struct RegionStore {
+    pub directory: PathBuf,
+    regions: HashMap<IVec3, Region>,
+    /// Chunks whose save could not be read. They are generated again instead,
+    /// but never saved, so what is on disk isn't overwritten.
+    unreadable: HashSet<IVec3>,
+    /// Regions whose file could not be read in the background, see [`RegionStore::insert_region`].
+    unreadable_regions: HashSet<IVec3>,
+    /// Regions whose last save failed. Their chunks are only tried again by the autosave,
+    /// see [`RegionStore::save_failed`].
+    failed: HashSet<IVec3>,
+    /// Whether the pending structure blocks could not be read, in which case they are never saved,
+    /// so what is on disk isn't overwritten.
+    pending_unreadable: bool,
+}
+
+impl Default for RegionStore {
+    fn default() -> Self {
+        Self::new(SAVE_DIRECTORY)
+    }
+}
+
+impl RegionStore {
+    pub fn new(directory: impl AsRef<Path>) -> Self {
+        return Self {
+            directory: directory.as_ref().to_path_buf(),
+            regions: HashMap::new(),
+            unreadable: HashSet::new(),
+            unreadable_regions: HashSet::new(),
+            failed: HashSet::new(),
+            pending_unreadable: false,
+        };
+    }
+
+    pub fn pending_writes_path(&self) -> PathBuf {
+        self.directory.join("pending.bin")
+    }
+
+    /// Reads the structure blocks that were waiting for their chunk when the world was last saved.
+    /// A missing file means nothing was waiting.
+    pub fn load_pending_writes(
+        &mut self,
+        block_registry: &BlockRegistry,
+    ) -> io::Result<PendingWrites> {
+        let loaded = match fs::read(self.pending_writes_path()) {
+            Ok(bytes) => decode_pending_writes(&bytes, block_registry),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(PendingWrites::default()),
+            Err(error) => Err(error),
+        };
+
+        self.pending_unreadable = loaded.is_err();
+
+        return loaded;
+    }
+
+    /// Rewrites the file of structure blocks waiting for their chunk. Call it whenever chunks
+    /// are saved, as the structures of a saved chunk are not placed again when it is loaded.
+    pub fn save_pending_writes(
+        &self,
+        pending_writes: &PendingWrites,
+        block_registry: &BlockRegistry,
+    ) -> io::Result<()> {
+        if self.pending_unreadable {
+            println!("Not saving the pending structure blocks, their save could not be read");
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.directory)?;
+
+        return write_atomically(
+            &self.pending_writes_path(),
+            &encode_pending_writes(pending_writes, block_registry),
+        );
+    }
+
+    pub fn region_path(&self, region: IVec3) -> PathBuf {
+        self.directory
+            .join(format!("r.{}.{}.{}.bin", region.x, region.y, region.z))
+    }
+
+    fn region(&mut self, region: IVec3) -> io::Result<&mut Region> {
+        if !self.regions.contains_key(&region) {
+            let loaded = read_region(&self.region_path(region))?;
+
+            self.unreadable_regions.remove(&region);
+            self.regions.insert(region, loaded);
+        }
+
+        return Ok(self.regions.get_mut(&region).unwrap());
+    }
+
+    /// Whether the chunks of a region can be loaded without waiting on the disk,
+    /// because its file was read or failed to be read.
+    pub fn is_loaded(&self, region: IVec3) -> bool {
+        self.regions.contains_key(&region) || self.unreadable_regions.contains(&region)
+    }
+
+    /// Keeps a region file read by [`LoadingRegions`]. A region already in memory is kept instead,
+    /// as it may hold chunks saved since the file was read. A region that couldn't be read
+    /// makes loading its chunks fail, so they are generated again and not saved over the file.
+    pub fn insert_region(&mut self, region: IVec3, loaded: io::Result<Region>) {
+        if self.regions.contains_key(&region) {
+            return;
+        }
+
+        match loaded {
+            Ok(loaded) => {
+                self.regions.insert(region, loaded);
+            }
+            Err(error) => {
+                println!("Could not read region {}: {}", region, error);
+                self.unreadable_regions.insert(region);
+            }
+        }
+    }
+
+    /// Returns the saved chunk at a chunk coordinate, or `None` if it was never saved.
+    /// A chunk that fails to load is left out of later saves, see [`RegionStore::save_chunks`].
+    pub fn load_chunk(
+        &mut self,
+        position: IVec3,
+        block_registry: &BlockRegistry,
+    ) -> io::Result<Option<Chunk>> {
+        let loaded = self.read_chunk(position, block_registry);
+
+        if loaded.is_err() {
+            self.unreadable.insert(position);
+        }
+
+        return loaded;
+    }
+
+    /// Whether a chunk was saved, even if its save could not be read.
+    pub fn contains(&mut self, position: IVec3) -> bool {
+        let (region, local) = chunk_to_region(position);
+
+        return self.unreadable.contains(&position)
+            || self
+                .region(region)
+                .is_ok_and(|region| region.chunks.contains_key(&local));
+    }
+
+    fn read_chunk(
+        &mut self,
+        position: IVec3,
+        block_registry: &BlockRegistry,
+    ) -> io::Result<Option<Chunk>> {
+        let (region, local) = chunk_to_region(position);
+
+        if self.unreadable_regions.contains(&region) {
+            return Err(io::Error::other(format!(
+                "region {} could not be read",
+                region
+            )));
+        }
+
+        let Some(bytes) = self.region(region)?.chunks.get(&local) else {
+            return Ok(None);
+        };
+
+        return decode_chunk(bytes, position, block_registry).map(Some);
+    }
+
+    /// Whether the last save of the region containing a chunk failed.
+    pub fn save_failed(&self, position: IVec3) -> bool {
+        let (region, _) = chunk_to_region(position);
+
+        return self.failed.contains(&region);
+    }
+
+    /// Stores the given chunks and rewrites the region files containing them, one by one.
+    /// Returns how many chunks were written and the regions that could not be written,
+    /// the others are saved regardless.
+    /// Chunks that failed to load are skipped, rather than replacing the data that couldn't be read.
+    pub fn save_chunks<'a>(
+        &mut self,
+        chunks: impl IntoIterator<Item = &'a Chunk>,
+        block_registry: &BlockRegistry,
+    ) -> (usize, Vec<RegionError>) {
+        let mut regions: HashMap<IVec3, Vec<&Chunk>> = HashMap::new();
+
+        for chunk in chunks {
+            if self.unreadable.contains(&chunk.position) {
+                println!(
+                    "Not saving chunk {}, its save could not be read",
+                    chunk.position
+                );
+                continue;
+            }
+
+            let (region, _) = chunk_to_region(chunk.position);
+            regions.entry(region).or_default().push(chunk);
+        }
+
+        let mut saved = 0;
+        let mut errors = Vec::new();
+
+        for (region, chunks) in regions {
+            match self.save_region(region, &chunks, block_registry) {
+                Ok(()) => {
+                    self.failed.remove(&region);
+                    saved += chunks.len();
+                }
+                Err(error) => {
+                    self.failed.insert(region);
+                    errors.push(RegionError {
+                        region,
+                        chunks: chunks.iter().map(|chunk| chunk.position).collect(),
+                        error,
+                    });
+                }
+            }
+        }
+
+        return (saved, errors);
+    }
+
+    fn save_region(
+        &mut self,
+        region: IVec3,
+        chunks: &[&Chunk],
+        block_registry: &BlockRegistry,
+    ) -> io::Result<()> {
+        let stored = self.region(region)?;
+
+        for chunk in chunks {
+            let (_, local) = chunk_to_region(chunk.position);
+            stored
+                .chunks
+                .insert(local, encode_chunk(chunk, block_registry));
+        }
+
+        let bytes = stored.to_bytes();
+
+        fs::create_dir_all(&self.directory)?;
+
+        return write_atomically(&self.region_path(region), &bytes);
+    }
+}
+
+/// Region files being read on the [`AsyncComputeTaskPool`], keyed by region coordinate,
+/// so streaming chunks in never waits on the disk.
+#[derive(Resource, Default)]
+pub struct LoadingRegions(pub HashMap<IVec3, Task<io::Result<Region>>>);
+
+impl LoadingRegions {
+    /// Starts reading a region file in the background, unless that is already underway.
+    pub fn load(&mut self, region: IVec3, region_store: &RegionStore) {
+        if self.0.contains_key(&region) {
+            return;
+        }
+
+        let path = region_store.region_path(region);
+        let task = AsyncComputeTaskPool::get().spawn(async move { read_region(&path) });
+
+        self.0.insert(region, task);
+    }
+}
+
+/// Hands the region files that finished reading to the [`RegionStore`].
+pub fn insert_loaded_regions(
+    mut loading_regions: ResMut<LoadingRegions>,
+    mut region_store: ResMut<RegionStore>,
+) {
+    loading_regions.0.retain(
+        |region, task| match future::block_on(future::poll_once(task)) {
+            Some(loaded) => {
+                region_store.insert_region(*region, loaded);
+                false
+            }
+            None => true,
+        },
+    );
+}
+
+#[derive(Resource)]
+pub struct AutosaveTimer(pub Timer);
+
+impl Default for AutosaveTimer {
+    fn default() -> Self {
+        Self(Timer::from_seconds(
+            AUTOSAVE_INTERVAL_SECS,
+            TimerMode::Repeating,
+        ))
+    }
+}
+
+/// Writes the chunks modified since the last save, periodically and when the game exits,
+/// along with the structure blocks waiting for their chunk.
+pub fn save_modified_chunks(
+    time: Res<Time>,
+    mut autosave_timer: ResMut<AutosaveTimer>,
+    mut exit_events: EventReader<AppExit>,
+    mut chunk_registry: ResMut<ChunkRegistry>,
+    mut region_store: ResMut<RegionStore>,
+    pending_writes: Res<PendingWrites>,
+    block_registry: Res<BlockRegistry>,
+) {
+    let exiting = exit_events.iter().count() > 0;
+
+    if !autosave_timer.0.tick(time.delta()).just_finished() && !exiting {
+        return;
+    }
+
+    if chunk_registry.modified.is_empty() {
+        return;
+    }
+
+    let modified: Vec<IVec3> = chunk_registry.modified.drain().collect();
+    let chunks = modified
+        .iter()
+        .filter_map(|position| chunk_registry.get_chunk(*position));
+
+    let (saved, errors) = region_store.save_chunks(chunks, &block_registry);
+
+    println!("Saved {} chunks", saved);
+
+    if let Err(error) = region_store.save_pending_writes(&pending_writes, &block_registry) {
+        println!("Could not save the pending structure blocks: {}", error);
+    }
+
+    for error in errors {
+        println!("Could not save region {}: {}", error.region, error.error);
+        // try again next time
+        chunk_registry.modified.extend(error.chunks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::tasks::TaskPool;
+
+    use super::*;
+    use crate::chunk::fluid::SOURCE_LEVEL;
+
+    fn chunks(block_registry: &BlockRegistry) -> [Chunk; 3] {
+        let stone = block_registry.by_id("stone").unwrap();
+        let gold = block_registry.by_id("gold").unwrap();
+        let water = block_registry.by_id("water").unwrap();
+
+        let empty = Chunk::empty(IVec3::new(-1, 0, 3));
+
+        let mut full = Chunk::empty(IVec3::new(-17, 2, 0));
+        full.blocks.fill(Some(stone));
+
+        let mut mixed = Chunk::empty(IVec3::new(5, -1, -20));
+        for (index, block) in mixed.blocks.iter_mut().enumerate() {
+            if index % 7 == 0 {
+                *block = Some(gold);
+            } else if index % 3 == 0 {
+                *block = Some(stone);
+            }
+        }
+        mixed.set_block(UVec3::new(1, 2, 4), Some(water));
+        mixed.set_fluid_level(UVec3::new(1, 2, 4), SOURCE_LEVEL - 2);
+        mixed.structure_sources.insert(IVec3::new(4, -1, -20));
+        mixed.structure_sources.insert(IVec3::new(5, -2, -21));
+
+        return [empty, full, mixed];
+    }
+
+    /// A save directory of its own for every test, as tests run in parallel.
+    fn save_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("region-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+
+        return directory;
+    }
+
+    fn assert_same_chunk(actual: &Chunk, expected: &Chunk) {
+        assert_eq!(actual.position, expected.position);
+        assert!(actual.blocks == expected.blocks);
+        assert_eq!(actual.fluid_levels, expected.fluid_levels);
+        assert_eq!(actual.structure_sources, expected.structure_sources);
+    }
+
+    #[test]
+    fn chunk_round_trip() {
+        let block_registry = BlockRegistry::from_assets();
+
+        for chunk in chunks(&block_registry) {
+            let bytes = encode_chunk(&chunk, &block_registry);
+            let decoded = decode_chunk(&bytes, chunk.position, &block_registry).unwrap();
+
+            assert_same_chunk(&decoded, &chunk);
+        }
+    }
+
+    #[test]
+    fn uniform_chunks_encode_to_a_single_run() {
+        let block_registry = BlockRegistry::from_assets();
+        let [empty, full, _] = chunks(&block_registry);
+
+        // palette of air, one run, no fluids or structure sources
+        assert_eq!(
+            encode_chunk(&empty, &block_registry).len(),
+            2 + 1 + 4 + 6 + 4 + 4
+        );
+        // palette of stone, one run, no fluids or structure sources
+        assert_eq!(
+            encode_chunk(&full, &block_registry).len(),
+            2 + 1 + "stone".len() + 4 + 6 + 4 + 4
+        );
+    }
+
+    #[test]
+    fn invalid_chunks_are_rejected() {
+        let block_registry = BlockRegistry::from_assets();
+        let [_, full, _] = chunks(&block_registry);
+        let bytes = encode_chunk(&full, &block_registry);
+
+        assert!(decode_chunk(&bytes[..bytes.len() - 1], IVec3::ZERO, &block_registry).is_err());
+        // a palette entry nobody knows
+        let mut unknown = bytes.clone();
+        unknown[3..8].copy_from_slice(b"stoke");
+        assert!(decode_chunk(&unknown, IVec3::ZERO, &block_registry).is_err());
+        // a run too short to fill the chunk
+        assert!(decode_chunk(
+            &[1, 0, 0, 1, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            IVec3::ZERO,
+            &block_registry
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn region_round_trip() {
+        let block_registry = BlockRegistry::from_assets();
+        let mut region = Region::default();
+
+        for chunk in chunks(&block_registry) {
+            let (_, local) = chunk_to_region(chunk.position);
+            region
+                .chunks
+                .insert(local, encode_chunk(&chunk, &block_registry));
+        }
+
+        assert_eq!(Region::from_bytes(&region.to_bytes()).unwrap(), region);
+        assert!(Region::from_bytes(b"VXRG").is_err());
+        assert!(Region::from_bytes(b"nope\x03\x00\x00\x00\x00").is_err());
+    }
+
+    #[test]
+    fn chunk_to_region_wraps_negative_coordinates() {
+        assert_eq!(
+            chunk_to_region(IVec3::new(-1, 0, 16)),
+            (IVec3::new(-1, 0, 1), UVec3::new(15, 0, 0))
+        );
+        assert_eq!(
+            chunk_to_region(IVec3::new(15, -16, -17)),
+            (IVec3::new(0, -1, -2), UVec3::new(15, 0, 15))
+        );
+    }
+
+    #[test]
+    fn saved_chunks_load_again() {
+        let block_registry = BlockRegistry::from_assets();
+        let directory = save_directory("load");
+        let chunks = chunks(&block_registry);
+
+        let (saved, errors) = RegionStore::new(&directory).save_chunks(&chunks, &block_registry);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(saved, chunks.len());
+
+        let mut region_store = RegionStore::new(&directory);
+        for chunk in chunks.iter() {
+            let loaded = region_store.load_chunk(chunk.position, &block_registry);
+
+            assert_same_chunk(&loaded.unwrap().unwrap(), chunk);
+        }
+        assert!(region_store
+            .load_chunk(IVec3::new(0, 0, 0), &block_registry)
+            .unwrap()
+            .is_none());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn failing_region_does_not_stop_the_others() {
+        let block_registry = BlockRegistry::from_assets();
+        let directory = save_directory("failing");
+        let chunks = chunks(&block_registry);
+        let mut region_store = RegionStore::new(&directory);
+
+        // a directory in the way of the temporary file makes writing the region fail
+        let (blocked, _) = chunk_to_region(chunks[1].position);
+        fs::create_dir_all(region_store.region_path(blocked).with_extension("tmp")).unwrap();
+
+        let (saved, errors) = region_store.save_chunks(&chunks, &block_registry);
+
+        assert_eq!(saved, 2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].region, blocked);
+        assert_eq!(errors[0].chunks, [chunks[1].position]);
+        assert!(region_store.save_failed(chunks[1].position));
+        assert!(!region_store.save_failed(chunks[0].position));
+
+        // saving it again once the way is clear lifts the failure
+        fs::remove_dir(region_store.region_path(blocked).with_extension("tmp")).unwrap();
+        let (saved, errors) = region_store.save_chunks([&chunks[1]], &block_registry);
+        assert_eq!(saved, 1);
+        assert!(errors.is_empty());
+        assert!(!region_store.save_failed(chunks[1].position));
+
+        let mut region_store = RegionStore::new(&directory);
+        for chunk in [&chunks[0], &chunks[2]] {
+            let loaded = region_store.load_chunk(chunk.position, &block_registry);
+
+            assert_same_chunk(&loaded.unwrap().unwrap(), chunk);
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn unreadable_chunks_are_not_overwritten() {
+        let block_registry = BlockRegistry::from_assets();
+        let directory = save_directory("unreadable");
+        let [_, full, _] = chunks(&block_registry);
+        let (region, local) = chunk_to_region(full.position);
+
+        let mut corrupt = Region::default();
+        corrupt.chunks.insert(local, vec![1, 2, 3]);
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            RegionStore::new(&directory).region_path(region),
+            corrupt.to_bytes(),
+        )
+        .unwrap();
+
+        let mut region_store = RegionStore::new(&directory);
+        assert!(region_store
+            .load_chunk(full.position, &block_registry)
+            .is_err());
+
+        // the chunk is generated again and changed, but saving it is skipped
+        let (saved, errors) = region_store.save_chunks([&full], &block_registry);
+        assert_eq!(saved, 0);
+        assert!(errors.is_empty());
+
+        let on_disk = fs::read(region_store.region_path(region)).unwrap();
+        assert_eq!(Region::from_bytes(&on_disk).unwrap(), corrupt);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    /// Reads a region the way streaming does, waiting for the background task to finish.
+    fn load_in_background(region_store: &mut RegionStore, region: IVec3) {
+        AsyncComputeTaskPool::init(TaskPool::new);
+
+        let mut loading_regions = LoadingRegions::default();
+        loading_regions.load(region, region_store);
+        assert!(!region_store.is_loaded(region));
+
+        let task = loading_regions.0.remove(&region).unwrap();
+        region_store.insert_region(region, future::block_on(task));
+        assert!(region_store.is_loaded(region));
+    }
+
+    #[test]
+    fn regions_load_in_the_background() {
+        let block_registry = BlockRegistry::from_assets();
+        let directory = save_directory("background");
+        let [_, _, mixed] = chunks(&block_registry);
+        let (region, _) = chunk_to_region(mixed.position);
+
+        let (_, errors) = RegionStore::new(&directory).save_chunks([&mixed], &block_registry);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let mut region_store = RegionStore::new(&directory);
+        load_in_background(&mut region_store, region);
+
+        // the file is not read again
+        fs::remove_dir_all(&directory).unwrap();
+        let loaded = region_store.load_chunk(mixed.position, &block_registry);
+        assert_same_chunk(&loaded.unwrap().unwrap(), &mixed);
+
+        // missing files are empty regions
+        load_in_background(&mut region_store, IVec3::new(7, 7, 7));
+        assert!(region_store
+            .load_chunk(
+                IVec3::new(7 * REGION_SIZE, 7 * REGION_SIZE, 7 * REGION_SIZE),
+                &block_registry
+            )
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn unreadable_regions_are_not_overwritten() {
+        let block_registry = BlockRegistry::from_assets();
+        let directory = save_directory("unreadable-region");
+        let [_, full, _] = chunks(&block_registry);
+        let (region, _) = chunk_to_region(full.position);
+        let mut region_store = RegionStore::new(&directory);
+
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(region_store.region_path(region), b"nope").unwrap();
+
+        load_in_background(&mut region_store, region);
+        assert!(region_store
+            .load_chunk(full.position, &block_registry)
+            .is_err());
+
+        let (saved, _) = region_store.save_chunks([&full], &block_registry);
+        assert_eq!(saved, 0);
+        assert_eq!(fs::read(region_store.region_path(region)).unwrap(), b"nope");
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn pending_writes_round_trip() {
+        let block_registry = BlockRegistry::from_assets();
+        let leaves = block_registry.by_id("leaves").unwrap();
+        let log = block_registry.by_id("log").unwrap();
+        let directory = save_directory("pending");
+
+        let mut pending_writes = PendingWrites::default();
+        let sources = pending_writes.0.entry(IVec3::new(-1, 0, 5)).or_default();
+        sources.insert(
+            IVec3::new(-2, 0, 5),
+            vec![(UVec3::new(0, 3, 15), leaves), (UVec3::new(0, 4, 15), log)],
+        );
+        sources.insert(IVec3::new(-1, 0, 4), vec![(UVec3::new(7, 0, 0), leaves)]);
+
+        let mut region_store = RegionStore::new(&directory);
+        assert!(region_store
+            .load_pending_writes(&block_registry)
+            .unwrap()
+            .0
+            .is_empty());
+        region_store
+            .save_pending_writes(&pending_writes, &block_registry)
+            .unwrap();
+
+        let loaded = RegionStore::new(&directory)
+            .load_pending_writes(&block_registry)
+            .unwrap();
+        assert_eq!(loaded.0, pending_writes.0);
+
+        // an unreadable file is left alone
+        fs::write(region_store.pending_writes_path(), b"nope").unwrap();
+        let mut region_store = RegionStore::new(&directory);
+        assert!(region_store.load_pending_writes(&block_registry).is_err());
+        region_store
+            .save_pending_writes(&pending_writes, &block_registry)
+            .unwrap();
+        assert_eq!(
+            fs::read(region_store.pending_writes_path()).unwrap(),
+            b"nope"
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+    utils::HashMap,
+};
+use futures_lite::future;
+use smooth_bevy_cameras::controllers::fps::FpsCameraController;
+
+use super::{
+    generation::TerrainGenerator,
+    region::{chunk_to_region, LoadingRegions, RegionStore},
+    structure::{PendingWrites, StructureSpill},
+    Chunk, ChunkRegistry,
+};
+use crate::block::BlockRegistry;
+
+/// Controls which chunks are kept loaded around the camera.
+#[derive(Resource, Debug, Clone)]
+pub struct ChunkStreaming {
+    /// Horizontal radius in chunks of the loaded area around the camera.
+    pub view_distance: i32,
+    /// How many chunks above and below the camera are loaded.
+    pub vertical_view_distance: i32,
+    /// Upper bound of chunks loaded or queued for generation per frame,
+    /// so moving fast doesn't cause hitches.
+    pub loads_per_frame: usize,
+    /// Upper bound of chunks being generated in the background at once.
+    pub max_generating: usize,
+}
+
+impl Default for ChunkStreaming {
+    fn default() -> Self {
+        Self {
+            view_distance: 8,
+            vertical_view_distance: 3,
+            loads_per_frame: 4,
+            max_generating: 32,
+        }
+    }
+}
+
+impl ChunkStreaming {
+    /// Whether a chunk is close enough to the chunk containing the camera to be loaded.
+    fn in_view(&self, position: IVec3, center: IVec3) -> bool {
+        in_cylinder(
+            position - center,
+            self.view_distance,
+            self.vertical_view_distance,
+        )
+    }
+
+    /// Chunks are only unloaded a little beyond the view distance, so walking
+    /// back and forth over a chunk border doesn't reload the same chunks.
+    fn in_unload_range(&self, position: IVec3, center: IVec3) -> bool {
+        in_cylinder(
+            position - center,
+            self.view_distance + 1,
+            self.vertical_view_distance + 1,
+        )
+    }
+}
+
+fn in_cylinder(offset: IVec3, radius: i32, half_height: i32) -> bool {
+    offset.x * offset.x + offset.z * offset.z <= radius * radius && offset.y.abs() <= half_height
+}
+
+/// Chunk the camera is in.
+fn camera_chunk(translation: Vec3) -> IVec3 {
+    let (chunk, _) = ChunkRegistry::world_to_chunk(translation.floor().as_ivec3());
+
+    return chunk;
+}
+
+/// Chunks being generated on the [`AsyncComputeTaskPool`], keyed by chunk coordinate.
+/// Dropping a task cancels it.
+#[derive(Resource, Default)]
+pub struct GeneratingChunks(pub HashMap<IVec3, Task<(Chunk, StructureSpill)>>);
+
+/// Adds a chunk to the registry, completing structures that neighbours placed into it.
+fn add_chunk(
+    mut chunk: Chunk,
+    pending_writes: &mut PendingWrites,
+    chunk_registry: &mut ChunkRegistry,
+) {
+    let position = chunk.position;
+    let completed = pending_writes.apply(&mut chunk);
+
+    chunk_registry.insert(chunk);
+
+    if completed {
+        chunk_registry.modified.insert(position);
+    }
+}
+
+/// Loads the closest missing chunks within the view distance, from disk if they were saved
+/// and by a background task otherwise, and unloads the ones that went out of range.
+/// Chunks wait for their region file to be read by [`LoadingRegions`] first.
+#[allow(clippy::too_many_arguments)]
+pub fn stream_chunks(
+    mut commands: Commands,
+    camera: Query<&Transform, With<FpsCameraController>>,
+    streaming: Res<ChunkStreaming>,
+    mut chunk_registry: ResMut<ChunkRegistry>,
+    mut region_store: ResMut<RegionStore>,
+    mut loading_regions: ResMut<LoadingRegions>,
+    mut generating_chunks: ResMut<GeneratingChunks>,
+    mut pending_writes: ResMut<PendingWrites>,
+    terrain_generator: Res<TerrainGenerator>,
+    block_registry: Res<BlockRegistry>,
+) {
+    let Ok(camera_transform) = camera.get_single() else {
+        return;
+    };
+
+    let center = camera_chunk(camera_transform.translation);
+
+    let out_of_range: Vec<IVec3> = chunk_registry
+        .chunks
+        .keys()
+        .copied()
+        .filter(|position| !streaming.in_unload_range(*position, center))
+        .collect();
+
+    // the chunk is not needed anymore, dropping the task cancels it
+    generating_chunks
+        .0
+        .retain(|position, _| streaming.in_unload_range(*position, center));
+
+    if !out_of_range.is_empty() {
+        unload_chunks(
+            &mut commands,
+            &out_of_range,
+            &mut chunk_registry,
+            &mut region_store,
+            &mut pending_writes,
+            &block_registry,
+        );
+    }
+
+    let view_distance = streaming.view_distance;
+    let vertical_view_distance = streaming.vertical_view_distance;
+    let mut missing: Vec<IVec3> = Vec::new();
+
+    for x in -view_distance..=view_distance {
+        for y in -vertical_view_distance..=vertical_view_distance {
+            for z in -view_distance..=view_distance {
+                let position = center + IVec3::new(x, y, z);
+
+                if streaming.in_view(position, center)
+                    && chunk_registry.get_chunk(position).is_none()
+                    && !generating_chunks.0.contains_key(&position)
+                {
+                    missing.push(position);
+                }
+            }
+        }
+    }
+
+    missing.sort_by_key(|position| {
+        let offset = *position - center;
+        offset.dot(offset)
+    });
+
+    let task_pool = AsyncComputeTaskPool::get();
+    let available = streaming
+        .max_generating
+        .saturating_sub(generating_chunks.0.len())
+        .min(streaming.loads_per_frame);
+    let mut started = 0;
+
+    for position in missing {
+        if started == available {
+            break;
+        }
+
+        let (region, _) = chunk_to_region(position);
+        if !region_store.is_loaded(region) {
+            loading_regions.load(region, &region_store);
+            continue;
+        }
+
+        started += 1;
+
+        // chunks changed by the player are saved, everything else is generated again
+        let saved = region_store
+            .load_chunk(position, &block_registry)
+            .unwrap_or_else(|error| {
+                // generated again instead, but not saved over what couldn't be read
+                println!("Could not load chunk {}: {}", position, error);
+                None
+            });
+
+        match saved {
+            Some(chunk) => add_chunk(chunk, &mut pending_writes, &mut chunk_registry),
+            None => {
+                let terrain_generator = terrain_generator.clone();
+                let task = task_pool.spawn(async move { terrain_generator.generate(position) });
+
+                generating_chunks.0.insert(position, task);
+            }
+        }
+    }
+}
+
+/// Adds the chunks whose generation finished to the registry, passing on the parts
+/// of their structures that reach into other chunks.
+pub fn insert_generated_chunks(
+    mut generating_chunks: ResMut<GeneratingChunks>,
+    mut pending_writes: ResMut<PendingWrites>,
+    mut chunk_registry: ResMut<ChunkRegistry>,
+) {
+    generating_chunks.0.retain(
+        |position, task| match future::block_on(future::poll_once(task)) {
+            Some((chunk, spill)) => {
+                add_chunk(chunk, &mut pending_writes, &mut chunk_registry);
+                pending_writes.distribute(*position, spill, &mut chunk_registry);
+                false
+            }
+            None => true,
+        },
+    );
+}
+
+/// Removes chunks along with their entities, saving the modified ones first.
+/// Chunks that could not be saved stay loaded. Those in regions that failed to save before
+/// are left to the autosave, rather than hitting the disk again every frame.
+fn unload_chunks(
+    commands: &mut Commands,
+    positions: &[IVec3],
+    chunk_registry: &mut ChunkRegistry,
+    region_store: &mut RegionStore,
+    pending_writes: &mut PendingWrites,
+    block_registry: &BlockRegistry,
+) {
+    let (mut unsaved, modified): (Vec<IVec3>, Vec<IVec3>) = positions
+        .iter()
+        .copied()
+        .filter(|position| chunk_registry.modified.contains(position))
+        .partition(|position| region_store.save_failed(*position));
+
+    let chunks = modified
+        .iter()
+        .filter_map(|position| chunk_registry.get_chunk(*position));
+
+    let (saved, errors) = region_store.save_chunks(chunks, block_registry);
+    if saved > 0 {
+        if let Err(error) = region_store.save_pending_writes(pending_writes, block_registry) {
+            println!("Could not save the pending structure blocks: {}", error);
+        }
+    }
+
+    for error in errors {
+        println!(
+            "Could not save region {} before unloading it: {}",
+            error.region, error.error
+        );
+        unsaved.extend(error.chunks);
+    }
+
+    for position in positions {
+        // keep the changes in memory rather than losing them, saving is tried again next time
+        if unsaved.contains(position) {
+            continue;
+        }
+
+        chunk_registry.modified.remove(position);
+
+        // a chunk that isn't saved is generated again, which queues its structures again
+        if !region_store.contains(*position) {
+            pending_writes.forget(*position);
+        }
+
+        if let Some(entities) = chunk_registry
+            .remove(*position)
+            .and_then(|chunk| chunk.entities)
+        {
+            commands.entity(entities.root).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,364 @@
+use std::path::Path;
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+use super::{biome::Biome, Chunk, ChunkRegistry};
+use crate::{
+    block::{BlockRegistry, BlockType},
+    util::{load_ron, LoadError},
+};
+
+/// Where the structure templates are read from, relative to the asset folder.
+pub const STRUCTURE_TEMPLATES_PATH: &str = "structures.ron";
+
+/// A structure as written in the structure templates file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StructureDefinition {
+    pub id: String,
+    pub biomes: Vec<Biome>,
+    pub density: f64,
+    pub origin: (i32, i32, i32),
+    pub palette: HashMap<char, String>,
+    /// Bottom to top, rows along z, characters along x.
+    pub layers: Vec<Vec<String>>,
+}
+
+/// A structure ready to be placed, its blocks resolved against the block registry.
+#[derive(Debug, Clone)]
+pub struct StructureTemplate {
+    pub biomes: Vec<Biome>,
+    /// Chance for a column to get this structure.
+    pub density: f64,
+    /// Blocks relative to the block placed right on top of the surface.
+    pub blocks: Vec<(IVec3, BlockType)>,
+}
+
+impl StructureTemplate {
+    pub fn new(definition: StructureDefinition, block_registry: &BlockRegistry) -> Self {
+        let origin = IVec3::new(
+            definition.origin.0,
+            definition.origin.1,
+            definition.origin.2,
+        );
+        let mut blocks = Vec::new();
+
+        for (y, layer) in definition.layers.iter().enumerate() {
+            for (z, row) in layer.iter().enumerate() {
+                for (x, character) in row.chars().enumerate() {
+                    let Some(id) = definition.palette.get(&character) else {
+                        continue;
+                    };
+
+                    let block_type = block_registry.by_id(id).unwrap_or_else(|| {
+                        panic!("Structure {:?} uses unknown block {:?}", definition.id, id)
+                    });
+
+                    blocks.push((
+                        IVec3::new(x as i32, y as i32, z as i32) - origin,
+                        block_type,
+                    ));
+                }
+            }
+        }
+
+        return Self {
+            biomes: definition.biomes,
+            density: definition.density,
+            blocks,
+        };
+    }
+
+    pub fn load(path: &Path, block_registry: &BlockRegistry) -> Result<Vec<Self>, LoadError> {
+        let definitions: Vec<StructureDefinition> = load_ron(path)?;
+
+        return Ok(definitions
+            .into_iter()
+            .map(|definition| Self::new(definition, block_registry))
+            .collect());
+    }
+}
+
+/// Deterministic value in `0.0..1.0` for a column, different for every seed and `salt`.
+/// Decides where structures go without keeping any state around.
+pub fn column_random(seed: u32, x: i32, z: i32, salt: u32) -> f64 {
+    // splitmix64 finalizer over all inputs
+    let mut value = (seed as u64) << 32 | salt as u64;
+    value ^= (x as u32 as u64) << 32 | z as u32 as u64;
+
+    value = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^= value >> 31;
+
+    return (value >> 11) as f64 / (1u64 << 53) as f64;
+}
+
+/// Blocks a generated chunk placed outside of itself, in world space.
+pub type StructureSpill = Vec<(IVec3, BlockType)>;
+
+/// Structure blocks waiting for their chunk to be generated or loaded, keyed by the chunk
+/// they go into and then by the chunk whose structures they belong to.
+/// Lets a structure straddling a chunk border be completed once the neighbour shows up.
+/// A chunk takes the blocks of each neighbour only once, see [`Chunk::structure_sources`].
+#[derive(Resource, Default, Debug)]
+pub struct PendingWrites(pub HashMap<IVec3, HashMap<IVec3, Vec<(UVec3, BlockType)>>>);
+
+impl PendingWrites {
+    /// Writes the blocks of the structures of a generated chunk (`source`) that lie outside of it,
+    /// straight into loaded chunks and into the queue for all others.
+    pub fn distribute(
+        &mut self,
+        source: IVec3,
+        spill: StructureSpill,
+        chunk_registry: &mut ChunkRegistry,
+    ) {
+        let mut targets: HashMap<IVec3, Vec<(UVec3, BlockType)>> = HashMap::new();
+
+        for (position, block_type) in spill {
+            let (chunk, local) = ChunkRegistry::world_to_chunk(position);
+            targets.entry(chunk).or_default().push((local, block_type));
+        }
+
+        for (target, writes) in targets {
+            let Some(chunk) = chunk_registry.get_chunk_mut(target) else {
+                // generating the source again queues the same blocks, they replace these
+                self.0.entry(target).or_default().insert(source, writes);
+                continue;
+            };
+
+            if !chunk.structure_sources.insert(source) {
+                continue;
+            }
+
+            for (local, block_type) in writes {
+                let position = ChunkRegistry::chunk_to_world(target, local.as_ivec3());
+
+                // structures only fill air
+                if chunk_registry.get_block(position).is_none() {
+                    chunk_registry.set_block(position, Some(block_type));
+                }
+            }
+
+            chunk_registry.modified.insert(target);
+        }
+    }
+
+    /// Applies the queued blocks of a chunk about to be added to the registry, skipping
+    /// those of sources it took blocks from before. Returns whether anything was applied,
+    /// in which case the chunk should be saved, as generating it again would not bring the blocks back.
+    pub fn apply(&mut self, chunk: &mut Chunk) -> bool {
+        let Some(sources) = self.0.remove(&chunk.position) else {
+            return false;
+        };
+
+        let mut applied = false;
+
+        for (source, writes) in sources {
+            if !chunk.structure_sources.insert(source) {
+                continue;
+            }
+
+            for (local, block_type) in writes {
+                if chunk.get_block(local).is_none() {
+                    chunk.set_block(local, Some(block_type));
+                }
+            }
+
+            applied = true;
+        }
+
+        return applied;
+    }
+
+    /// Drops the queued blocks of a chunk's structures, for when it is unloaded without being saved.
+    /// They are queued again when the chunk is generated again.
+    pub fn forget(&mut self, source: IVec3) {
+        self.0.retain(|_, sources| {
+            sources.remove(&source);
+            !sources.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::chunk::{
+        caves::{CaveKind, CaveSettings},
+        generation::{TerrainGenerator, WorldSeed},
+        region::RegionStore,
+    };
+
+    const SOURCE: IVec3 = IVec3::ZERO;
+    const TARGET: IVec3 = IVec3::X;
+
+    fn leaves() -> BlockType {
+        return BlockRegistry::from_assets().by_id("leaves").unwrap();
+    }
+
+    /// Two blocks of a tree in the source chunk reaching into the target chunk.
+    fn spill() -> StructureSpill {
+        vec![
+            (IVec3::new(16, 5, 3), leaves()),
+            (IVec3::new(17, 5, 3), leaves()),
+        ]
+    }
+
+    #[test]
+    fn loaded_chunks_take_blocks_once() {
+        let mut chunk_registry = ChunkRegistry::default();
+        let mut pending_writes = PendingWrites::default();
+        chunk_registry.insert(Chunk::empty(TARGET));
+
+        pending_writes.distribute(SOURCE, spill(), &mut chunk_registry);
+
+        assert_eq!(
+            chunk_registry.get_block(IVec3::new(16, 5, 3)),
+            Some(leaves())
+        );
+        assert!(chunk_registry.modified.contains(&TARGET));
+
+        // broken by the player, then the source is generated again
+        chunk_registry.set_block(IVec3::new(16, 5, 3), None);
+        pending_writes.distribute(SOURCE, spill(), &mut chunk_registry);
+
+        assert_eq!(chunk_registry.get_block(IVec3::new(16, 5, 3)), None);
+        assert_eq!(
+            chunk_registry.get_block(IVec3::new(17, 5, 3)),
+            Some(leaves())
+        );
+        assert!(pending_writes.0.is_empty());
+    }
+
+    #[test]
+    fn queued_once_per_source() {
+        let mut chunk_registry = ChunkRegistry::default();
+        let mut pending_writes = PendingWrites::default();
+
+        pending_writes.distribute(SOURCE, spill(), &mut chunk_registry);
+        pending_writes.distribute(SOURCE, spill(), &mut chunk_registry);
+
+        assert_eq!(pending_writes.0.len(), 1);
+        assert_eq!(pending_writes.0[&TARGET][&SOURCE].len(), 2);
+
+        let mut target = Chunk::empty(TARGET);
+        assert!(pending_writes.apply(&mut target));
+
+        assert_eq!(target.get_block(UVec3::new(0, 5, 3)), Some(leaves()));
+        assert!(target.structure_sources.contains(&SOURCE));
+        assert!(pending_writes.0.is_empty());
+    }
+
+    #[test]
+    fn saved_chunks_skip_blocks_they_took_before() {
+        let mut chunk_registry = ChunkRegistry::default();
+        let mut pending_writes = PendingWrites::default();
+
+        pending_writes.distribute(SOURCE, spill(), &mut chunk_registry);
+
+        // loaded from disk, where the leaves were broken after they were placed
+        let mut target = Chunk::empty(TARGET);
+        target.structure_sources.insert(SOURCE);
+
+        assert!(!pending_writes.apply(&mut target));
+        assert_eq!(target.get_block(UVec3::new(0, 5, 3)), None);
+        assert!(pending_writes.0.is_empty());
+    }
+
+    #[test]
+    fn forgetting_a_source_drops_its_blocks() {
+        let mut chunk_registry = ChunkRegistry::default();
+        let mut pending_writes = PendingWrites::default();
+        let other = IVec3::new(2, 0, 0);
+
+        pending_writes.distribute(SOURCE, spill(), &mut chunk_registry);
+        pending_writes.distribute(
+            other,
+            vec![(IVec3::new(20, 5, 3), leaves())],
+            &mut chunk_registry,
+        );
+
+        pending_writes.forget(SOURCE);
+        assert_eq!(pending_writes.0.len(), 1);
+        assert!(!pending_writes.0[&TARGET].contains_key(&SOURCE));
+
+        pending_writes.forget(other);
+        assert!(pending_writes.0.is_empty());
+    }
+
+    #[test]
+    fn pending_writes_survive_a_restart() {
+        let block_registry = BlockRegistry::from_assets();
+        let definitions: Vec<StructureDefinition> =
+            ron::from_str(include_str!("../../assets/structures.ron")).unwrap();
+        let structures = definitions
+            .into_iter()
+            .map(|definition| StructureTemplate::new(definition, &block_registry))
+            .collect();
+        let cave_settings = CaveSettings {
+            kind: CaveKind::Worm { radius: 0.06 },
+            min_height: -256,
+        };
+        let terrain_generator =
+            TerrainGenerator::new(WorldSeed(7), cave_settings, structures, &block_registry);
+        let directory =
+            std::env::temp_dir().join(format!("structure-test-{}-restart", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+
+        // a chunk whose structures reach into a neighbour
+        let (source, source_chunk, spill) = (0..64)
+            .flat_map(|x| (-2..=2).map(move |y| IVec3::new(x, y, 0)))
+            .map(|position| {
+                let (chunk, spill) = terrain_generator.generate(position);
+                (position, chunk, spill)
+            })
+            .find(|(_, _, spill)| !spill.is_empty())
+            .expect("no structure reaches out of its chunk");
+        let (target, _) = ChunkRegistry::world_to_chunk(spill[0].0);
+
+        {
+            let mut chunk_registry = ChunkRegistry::default();
+            let mut pending_writes = PendingWrites::default();
+            let mut region_store = RegionStore::new(&directory);
+
+            chunk_registry.insert(source_chunk);
+            pending_writes.distribute(source, spill.clone(), &mut chunk_registry);
+
+            // the player changed the source, so it is saved, while the target was never generated
+            let (saved, errors) =
+                region_store.save_chunks(chunk_registry.get_chunk(source), &block_registry);
+            assert_eq!(saved, 1);
+            assert!(errors.is_empty(), "{:?}", errors);
+            region_store
+                .save_pending_writes(&pending_writes, &block_registry)
+                .unwrap();
+        }
+
+        // after a restart the source comes from disk and doesn't place its structures again
+        let mut region_store = RegionStore::new(&directory);
+        let mut pending_writes = region_store.load_pending_writes(&block_registry).unwrap();
+        assert!(region_store
+            .load_chunk(source, &block_registry)
+            .unwrap()
+            .is_some());
+
+        let (generated, _) = terrain_generator.generate(target);
+        let mut target_chunk = generated.clone();
+        assert!(pending_writes.apply(&mut target_chunk));
+        assert!(target_chunk.structure_sources.contains(&source));
+
+        for (position, _) in spill.iter() {
+            let (chunk, local) = ChunkRegistry::world_to_chunk(*position);
+
+            // structures only fill air
+            if chunk == target && generated.get_block(local).is_none() {
+                assert!(target_chunk.get_block(local).is_some());
+            }
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,16 +1,22 @@
 use bevy_mod_outline::*;
 
-use bevy::{
-    input::{
-        keyboard::KeyboardInput,
-        mouse::{MouseButtonInput, MouseMotion},
-        ButtonState,
-    },
-    prelude::*,
+use bevy::{input::mouse::MouseMotion, prelude::*};
+
+use bevy_rapier3d::prelude::*;
+use smooth_bevy_cameras::controllers::fps::FpsCameraController;
+
+use crate::{
+    block::*,
+    chunk::{ChunkEntity, ChunkRegistry},
+    input::Action,
+    inventory::Inventory,
+    player::Player,
+    state::GameState,
+    ui::inventory_screen::inventory_screen_closed,
 };
-use bevy_rapier3d::{prelude::*, render::ColliderDebugColor};
 
-use crate::{block::*, ui::inventory::SelectInventorySlotEvent};
+/// How far away (in blocks) the player can target blocks.
+const REACH_DISTANCE: f32 = 8.0;
 
 pub struct EventSystemPlugin;
 
@@ -19,174 +25,293 @@ impl Plugin for EventSystemPlugin {
         app.add_event::<HighlightBlock>()
             .add_event::<RemoveBlockHighlight>()
             .add_event::<BlockSpawnEvent>()
-            .add_event::<SelectBlockEvent>()
+            .add_event::<BlockBreakEvent>()
+            .add_event::<BlockPlacementRejected>()
             .add_plugin(OutlinePlugin)
             .insert_resource(Msaa::Sample4)
-            .add_system(spawn_block)
-            .add_system(select_block_to_spawn)
-            .add_system(highlight_block_at_crosshair)
+            .add_system(highlight_block_at_crosshair.in_set(OnUpdate(GameState::InGame)))
             .add_system(highlight_block)
-            .add_system(mouse_button_events)
-            .add_system(spawn_block.run_if(resource_exists::<BlockMaterialStore>()))
-            .insert_resource(BlockHighlightEventPrevious::default());
+            // clicks on the inventory screen are meant for its slots
+            .add_system(
+                mouse_button_events
+                    .run_if(inventory_screen_closed)
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(spawn_block)
+            .add_system(break_block)
+            .add_startup_system(initialize_block_highlight)
+            .insert_resource(HighlightedBlock::default());
+
+        #[cfg(debug_assertions)]
+        app.add_system(spawn_debug_cube.in_set(OnUpdate(GameState::InGame)));
     }
 }
 
 #[derive(Debug)]
-pub struct SelectBlockEvent(pub BlockType);
-
-#[allow(dead_code)]
 pub struct BlockSpawnEvent {
-    entity: Entity,
-    position: Vec3,
-    color: Color,
+    /// World space position of the (empty) cell to place the block in.
+    position: IVec3,
+}
+
+/// Sent instead of placing a block when its cell can't take it, the reason is shown on screen.
+#[derive(Debug)]
+pub struct BlockPlacementRejected {
+    pub reason: PlacementRejection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlacementRejection {
+    /// Another block is already there. Fluids don't count, blocks are placed into them.
+    Occupied,
+    /// The block would end up inside the player, or around the camera when flying.
+    IntersectsPlayer,
+    /// The block would end up inside some other entity with a collider.
+    IntersectsEntity(Entity),
+    /// The selected hotbar slot is empty.
+    NothingSelected,
+}
+
+#[derive(Debug)]
+pub struct BlockBreakEvent {
+    /// World space position of the block to remove.
+    position: IVec3,
 }
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub struct HighlightBlock {
-    entity: Entity,
-    intersection: RayIntersection,
+    /// World space position of the targeted block.
+    position: IVec3,
 }
 
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct RemoveBlockHighlight;
 
-#[derive(Resource, Default, Debug)]
-struct BlockHighlightEventPrevious {
-    entity: Option<Entity>,
-    material: Option<Handle<StandardMaterial>>,
-}
-
+/// Holds the outlined cube that is moved onto whichever block is being looked at,
+/// as chunks are rendered as a single mesh and can't be outlined block by block.
 #[derive(Resource, Default, Debug)]
 struct HighlightedBlock {
     entity: Option<Entity>,
 }
 
-// TODO some blocks might prefer to stay in the shadows!
-#[derive(Component)]
-pub struct Highlightable;
+/// Checks whether a block can be placed in a cell, it has to be free of other blocks
+/// and of the colliders of the player and other entities. Chunk colliders are left out,
+/// the blocks themselves are already checked.
+fn validate_placement(
+    position: IVec3,
+    chunk_registry: &ChunkRegistry,
+    block_registry: &BlockRegistry,
+    rapier_context: &RapierContext,
+    players: &Query<Entity, With<Player>>,
+    chunk_entities: &Query<(), With<ChunkEntity>>,
+    camera: Option<&GlobalTransform>,
+) -> Result<(), PlacementRejection> {
+    if let Some(block_type) = chunk_registry.get_block(position) {
+        if !block_registry.is_fluid(block_type) {
+            return Err(PlacementRejection::Occupied);
+        }
+    }
 
-fn select_block_to_spawn(
-    mut input: EventReader<KeyboardInput>,
-    mut select_block: EventWriter<SelectBlockEvent>,
-    mut select_inv: EventWriter<SelectInventorySlotEvent>,
-) {
-    for event in input.iter() {
-        match event.state {
-            ButtonState::Pressed => match event.key_code {
-                Some(code) => match code {
-                    KeyCode::Key1 => {
-                        select_block.send(SelectBlockEvent(BlockType::Stone));
-                        select_inv.send(SelectInventorySlotEvent(0));
-                    }
-                    KeyCode::Key2 => {
-                        select_block.send(SelectBlockEvent(BlockType::Soil));
-                        select_inv.send(SelectInventorySlotEvent(1));
-                    }
-                    KeyCode::Key3 => {
-                        select_block.send(SelectBlockEvent(BlockType::Grass));
-                        select_inv.send(SelectInventorySlotEvent(2));
-                    }
-                    _ => {}
-                },
-                None => {}
-            },
-            ButtonState::Released => {}
+    let center = position.as_vec3() + Vec3::splat(0.5);
+
+    if let Some(camera) = camera {
+        if (camera.translation() - center).abs().max_element() < 0.5 {
+            return Err(PlacementRejection::IntersectsPlayer);
         }
     }
+
+    // slightly smaller than the cell, so colliders merely touching it don't count
+    let cell = Collider::cuboid(0.49, 0.49, 0.49);
+    let not_chunk = |entity| !chunk_entities.contains(entity);
+    let filter = QueryFilter::default()
+        .exclude_sensors()
+        .predicate(&not_chunk);
+
+    let mut intersecting = None;
+    rapier_context.intersections_with_shape(center, Quat::IDENTITY, &cell, filter, |entity| {
+        intersecting = Some(entity);
+        // the player is reported over anything else
+        !players.contains(entity)
+    });
+
+    return match intersecting {
+        Some(entity) if players.contains(entity) => Err(PlacementRejection::IntersectsPlayer),
+        Some(entity) => Err(PlacementRejection::IntersectsEntity(entity)),
+        None => Ok(()),
+    };
 }
 
+#[allow(clippy::too_many_arguments)]
 fn spawn_block(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
     mut spawn_block: EventReader<BlockSpawnEvent>,
-    material_store: ResMut<BlockMaterialStore>,
-    selected_block: Res<SelectedBlock>,
+    mut placement_rejected: EventWriter<BlockPlacementRejected>,
+    mut chunk_registry: ResMut<ChunkRegistry>,
+    mut inventory: ResMut<Inventory>,
+    block_registry: Res<BlockRegistry>,
+    rapier_context: Res<RapierContext>,
+    players: Query<Entity, With<Player>>,
+    chunk_entities: Query<(), With<ChunkEntity>>,
+    cameras: Query<(&GlobalTransform, &FpsCameraController)>,
 ) {
+    // a flying camera has no collider of its own
+    let camera = cameras
+        .get_single()
+        .ok()
+        .filter(|(_, controller)| controller.enabled)
+        .map(|(transform, _)| transform);
+
     for spawn in spawn_block.iter() {
         let position = spawn.position;
+        let selected = inventory.selected;
 
-        println!("RECEIVING EVENT");
+        // the block placed is whatever the selected hotbar slot holds
+        let Some(stack) = inventory.items[selected].contains else {
+            placement_rejected.send(BlockPlacementRejected {
+                reason: PlacementRejection::NothingSelected,
+            });
+            continue;
+        };
 
-        Block::create(
-            selected_block.0,
-            &material_store,
-            &mut commands,
-            &mut meshes,
+        if let Err(reason) = validate_placement(
             position,
-        );
+            &chunk_registry,
+            &block_registry,
+            &rapier_context,
+            &players,
+            &chunk_entities,
+            camera,
+        ) {
+            debug!("Can't place a block at {}: {:?}", position, reason);
+            placement_rejected.send(BlockPlacementRejected { reason });
+            continue;
+        }
+
+        // the block is only used up once it is in the world
+        if chunk_registry.set_block(position, Some(stack.block)) {
+            inventory.remove(selected, 1);
+        }
     }
 
     spawn_block.clear();
 }
 
-// TODO surely we can do better
+/// Breaks blocks into the inventory. Blocks stay where they are if it has no room for them.
+fn break_block(
+    mut break_block: EventReader<BlockBreakEvent>,
+    mut chunk_registry: ResMut<ChunkRegistry>,
+    mut inventory: ResMut<Inventory>,
+    block_registry: Res<BlockRegistry>,
+) {
+    for event in break_block.iter() {
+        let Some(block_type) = chunk_registry.get_block(event.position) else {
+            continue;
+        };
+
+        // the block stays where it is rather than being lost
+        if inventory.add(block_type, 1) > 0 {
+            println!(
+                "Inventory is full, can't pick up {}",
+                block_registry.get(block_type).name
+            );
+            continue;
+        }
+
+        chunk_registry.set_block(event.position, None);
+    }
+}
+
+fn initialize_block_highlight(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut highlighted_block: ResMut<HighlightedBlock>,
+) {
+    let entity = commands
+        .spawn(PbrBundle {
+            // slightly larger than a block, so it does not fight with the chunk's faces
+            mesh: meshes.add(Mesh::from(shape::Cube { size: 1.01 })),
+            material: materials.add(StandardMaterial {
+                base_color: Color::rgba(0.0, 0.0, 0.0, 0.0),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            }),
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(OutlineBundle {
+            outline: OutlineVolume {
+                visible: true,
+                colour: Color::rgba(0.8, 0.4, 0.8, 1.0),
+                width: 2.0,
+            },
+            ..default()
+        })
+        .insert(Name::new("Block highlight"))
+        .id();
+
+    highlighted_block.entity = Some(entity);
+}
+
 fn highlight_block(
     mut remove_block_highlight: EventReader<RemoveBlockHighlight>,
     mut highlight_block: EventReader<HighlightBlock>,
-    mut previously_highlighted: ResMut<BlockHighlightEventPrevious>,
-    mut commands: Commands,
+    highlighted_block: Res<HighlightedBlock>,
+    mut highlight_query: Query<(&mut Transform, &mut Visibility)>,
 ) {
+    let Some(entity) = highlighted_block.entity else {
+        return;
+    };
+
+    let Ok((mut transform, mut visibility)) = highlight_query.get_mut(entity) else {
+        return;
+    };
+
     for _ in remove_block_highlight.iter() {
-        if let Some(previous_entity) = previously_highlighted.entity {
-            commands.entity(previous_entity).remove::<OutlineBundle>();
-        }
+        *visibility = Visibility::Hidden;
     }
 
     for highlight_event in highlight_block.iter() {
-        if let Some(previous_entity) = previously_highlighted.entity {
-            commands.entity(previous_entity).remove::<OutlineBundle>();
-        }
+        transform.translation = highlight_event.position.as_vec3() + Vec3::splat(0.5);
+        *visibility = Visibility::Visible;
+    }
+}
 
-        commands
-            .entity(highlight_event.entity)
-            .remove::<OutlineBundle>();
-
-        commands
-            .entity(highlight_event.entity)
-            .insert(OutlineBundle {
-                outline: OutlineVolume {
-                    visible: true,
-                    colour: Color::rgba(0.8, 0.4, 0.8, 1.0),
-                    width: 2.0,
-                },
-                ..default()
-            });
+/// Casts a ray from the camera through the crosshair at the center of the window.
+fn crosshair_ray(
+    windows: &Query<&mut Window>,
+    camera_query: &Query<(&Camera, &GlobalTransform), With<Camera>>,
+) -> Option<Ray> {
+    let (camera, camera_transform) = camera_query.single();
 
-        previously_highlighted.entity = Some(highlight_event.entity);
-    }
+    let window = windows.get_single().unwrap();
+    let window_center = Vec2::new(window.width() / 2.0, window.height() / 2.0);
+
+    // use this for cursor position instead of viewport center
+    // let cursor = window.cursor_position().unwrap();
+
+    camera.viewport_to_world(camera_transform, window_center)
 }
 
 /// Triggers a BlockHighlightEvent.
 pub fn highlight_block_at_crosshair(
     windows: Query<&mut Window>,
-    rapier_context: Res<RapierContext>,
+    chunk_registry: Res<ChunkRegistry>,
+    block_registry: Res<BlockRegistry>,
     camera_query: Query<(&Camera, &GlobalTransform), With<Camera>>,
     mut highlight_block: EventWriter<HighlightBlock>,
     mut remove_block_highlight: EventWriter<RemoveBlockHighlight>,
     _: EventReader<MouseMotion>,
 ) {
-    let (camera, camera_transform) = camera_query.single();
+    let Some(ray) = crosshair_ray(&windows, &camera_query) else {
+        return;
+    };
 
-    let window = windows.get_single().unwrap();
-    let window_center = Vec2::new(window.width() / 2.0, window.height() / 2.0);
-
-    let ray = camera
-        .viewport_to_world(camera_transform, window_center)
-        .unwrap();
-
-    if let Some((entity, intersection)) = rapier_context.cast_ray_and_get_normal(
-        ray.origin,
-        ray.direction,
-        f32::MAX,
-        true,
-        QueryFilter::new(),
-    ) {
+    if let Some(hit) =
+        chunk_registry.raycast(ray.origin, ray.direction, REACH_DISTANCE, &block_registry)
+    {
         highlight_block.send(HighlightBlock {
-            entity,
-            intersection,
+            position: hit.block,
         });
     } else {
         remove_block_highlight.send(RemoveBlockHighlight);
@@ -195,75 +320,58 @@ pub fn highlight_block_at_crosshair(
 
 fn mouse_button_events(
     windows: Query<&mut Window>,
-    rapier_context: Res<RapierContext>,
     camera_query: Query<(&Camera, &GlobalTransform), With<Camera>>,
-    mut commands: Commands,
-    mut mouse_button: EventReader<MouseButtonInput>,
+    actions: Res<Input<Action>>,
     mut block_spawn: EventWriter<BlockSpawnEvent>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    material_store: ResMut<BlockMaterialStore>,
+    mut block_break: EventWriter<BlockBreakEvent>,
+    chunk_registry: Res<ChunkRegistry>,
+    block_registry: Res<BlockRegistry>,
 ) {
-    for ev in mouse_button.iter() {
-        match ev.state {
-            ButtonState::Pressed => {
-                if ev.button == MouseButton::Left {
-                    let (camera, camera_transform) = camera_query.single();
-                    println!("SENDING EVENT");
-
-                    let window = windows.get_single().unwrap();
-                    let window_center = Vec2::new(window.width() / 2.0, window.height() / 2.0);
-
-                    // use this for cursor position instead of viewport center
-                    // let cursor = window.cursor_position().unwrap();
-
-                    let ray = camera
-                        .viewport_to_world(camera_transform, window_center)
-                        .unwrap();
-
-                    if let Some((entity, intersection)) = rapier_context.cast_ray_and_get_normal(
-                        ray.origin,
-                        ray.direction,
-                        f32::MAX,
-                        true,
-                        QueryFilter::new(),
-                    ) {
-                        let hit_point = intersection.point;
-                        let hit_normal = intersection.normal;
-
-                        commands
-                            .entity(entity)
-                            .insert(ColliderDebugColor(Color::GREEN));
-
-                        block_spawn.send(BlockSpawnEvent {
-                            // while flooring the value guarantees axis-aligned placement, it does not necessarily guarantee correct axis :]
-                            position: (hit_point + hit_normal).round(),
-                            color: Color::YELLOW,
-                            entity,
-                        });
-                    }
-                }
-
-                if ev.button == MouseButton::Right {
-                    for x in -5..5 {
-                        for y in -5..5 {
-                            for z in -5..5 {
-                                Block::create(
-                                    BlockType::Stone,
-                                    &material_store,
-                                    &mut commands,
-                                    &mut meshes,
-                                    Vec3 {
-                                        x: x as f32,
-                                        y: y as f32,
-                                        z: z as f32,
-                                    },
-                                );
-                            }
-                        }
-                    }
-                }
+    let place = actions.just_pressed(Action::PlaceBlock);
+
+    if !place && !actions.just_pressed(Action::BreakBlock) {
+        return;
+    }
+
+    let Some(ray) = crosshair_ray(&windows, &camera_query) else {
+        return;
+    };
+
+    let Some(hit) =
+        chunk_registry.raycast(ray.origin, ray.direction, REACH_DISTANCE, &block_registry)
+    else {
+        return;
+    };
+
+    if place {
+        block_spawn.send(BlockSpawnEvent {
+            position: hit.adjacent,
+        });
+    } else {
+        block_break.send(BlockBreakEvent {
+            position: hit.block,
+        });
+    }
+}
+
+/// Fills a 10x10x10 cube of stone around the origin, for testing only.
+#[cfg(debug_assertions)]
+fn spawn_debug_cube(
+    actions: Res<Input<Action>>,
+    mut chunk_registry: ResMut<ChunkRegistry>,
+    block_registry: Res<BlockRegistry>,
+) {
+    if !actions.just_pressed(Action::DebugCube) {
+        return;
+    }
+
+    let stone = block_registry.by_id("stone").unwrap();
+
+    for x in -5..5 {
+        for y in -5..5 {
+            for z in -5..5 {
+                chunk_registry.set_block(IVec3::new(x, y, z), Some(stone));
             }
-            ButtonState::Released => {}
         }
     }
 }
@@ -0,0 +1,248 @@
+use std::path::Path;
+
+use bevy::{asset::FileAssetIo, input::InputSystem, prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+use crate::util::{load_ron, LoadError};
+
+/// Where the input map is read from, relative to the asset folder.
+const INPUT_MAP_PATH: &str = "input.ron";
+
+/// Turns keys and mouse buttons into [`Action`]s, which systems read through an
+/// `Input<Action>` resource instead of looking at the raw input.
+/// Insert an [`InputMap`] before adding the plugin to use it instead of the one on disk.
+pub struct InputMapPlugin;
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.world.get_resource_or_insert_with(|| {
+            InputMap::load(
+                &FileAssetIo::get_base_path()
+                    .join("assets")
+                    .join(INPUT_MAP_PATH),
+            )
+            .unwrap_or_else(|error| panic!("Could not load the input map. {}", error))
+        });
+
+        app.insert_resource(Input::<Action>::default()).add_system(
+            resolve_actions
+                .in_base_set(CoreSet::PreUpdate)
+                .after(InputSystem),
+        );
+    }
+}
+
+/// Something the player does, bound to keys and buttons by the [`InputMap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Sprint,
+    Crouch,
+    /// Rises while flying.
+    FlyUp,
+    /// Sinks while flying.
+    FlyDown,
+    /// Switches between walking and flying.
+    ToggleFlying,
+    PlaceBlock,
+    BreakBlock,
+    /// Selects a hotbar slot, counting from `0`.
+    HotbarSlot(usize),
+    ToggleInventory,
+    /// Picks up or puts down a stack on the inventory screen.
+    PickStack,
+    /// Picks up half a stack or puts down a single item on the inventory screen.
+    SplitStack,
+    /// Held while picking a stack to move it between the hotbar and the storage instead.
+    QuickMove,
+    GrabCursor,
+    /// Pauses the game, or resumes it when paused.
+    Pause,
+    /// Fills a cube of stone around the origin, only in debug builds.
+    DebugCube,
+}
+
+/// A key or mouse button an [`Action`] is bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+/// Which keys and buttons trigger which actions, read from the input map file at startup
+/// so controls can be changed without recompiling. An action is held while any of its
+/// bindings is, actions without bindings never happen.
+#[derive(Resource, Debug, Clone, Default, Deserialize)]
+pub struct InputMap {
+    pub bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl InputMap {
+    pub fn load(path: &Path) -> Result<Self, LoadError> {
+        return load_ron(path);
+    }
+
+    /// Whether any of the bindings of an action is held down.
+    pub fn is_held(
+        &self,
+        action: Action,
+        keys: &Input<KeyCode>,
+        mouse_buttons: &Input<MouseButton>,
+    ) -> bool {
+        let Some(bindings) = self.bindings.get(&action) else {
+            return false;
+        };
+
+        return bindings.iter().any(|binding| match binding {
+            Binding::Key(key_code) => keys.pressed(*key_code),
+            Binding::Mouse(button) => mouse_buttons.pressed(*button),
+        });
+    }
+}
+
+/// Presses and releases actions as their bindings are, right after the raw input was read.
+pub fn resolve_actions(
+    input_map: Res<InputMap>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut actions: ResMut<Input<Action>>,
+) {
+    actions.clear();
+
+    for action in input_map.bindings.keys().copied() {
+        let held = input_map.is_held(action, &keys, &mouse_buttons);
+
+        if held && !actions.pressed(action) {
+            actions.press(action);
+        } else if !held && actions.pressed(action) {
+            actions.release(action);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An app with just the input map plugin, its keys and buttons pressed by hand.
+    fn app(input_map: &str) -> App {
+        let mut app = App::new();
+        app.insert_resource(ron::from_str::<InputMap>(input_map).unwrap())
+            .insert_resource(Input::<KeyCode>::default())
+            .insert_resource(Input::<MouseButton>::default())
+            .add_plugin(InputMapPlugin);
+
+        return app;
+    }
+
+    fn press_key(app: &mut App, key_code: KeyCode) {
+        app.world.resource_mut::<Input<KeyCode>>().press(key_code);
+        app.update();
+    }
+
+    fn release_key(app: &mut App, key_code: KeyCode) {
+        app.world.resource_mut::<Input<KeyCode>>().release(key_code);
+        app.update();
+    }
+
+    fn actions(app: &App) -> &Input<Action> {
+        return app.world.resource::<Input<Action>>();
+    }
+
+    #[test]
+    fn keys_press_and_release_their_actions() {
+        let mut app = app("(bindings: { MoveForward: [Key(W)], Jump: [Key(Space)] })");
+
+        press_key(&mut app, KeyCode::W);
+        assert!(actions(&app).just_pressed(Action::MoveForward));
+        assert!(!actions(&app).pressed(Action::Jump));
+
+        app.update();
+        assert!(actions(&app).pressed(Action::MoveForward));
+        assert!(!actions(&app).just_pressed(Action::MoveForward));
+
+        release_key(&mut app, KeyCode::W);
+        assert!(actions(&app).just_released(Action::MoveForward));
+        assert!(!actions(&app).pressed(Action::MoveForward));
+
+        app.update();
+        assert!(!actions(&app).just_released(Action::MoveForward));
+    }
+
+    #[test]
+    fn any_binding_holds_the_action() {
+        let mut app = app("(bindings: { PlaceBlock: [Mouse(Right), Key(Q)] })");
+
+        app.world
+            .resource_mut::<Input<MouseButton>>()
+            .press(MouseButton::Right);
+        app.update();
+        assert!(actions(&app).just_pressed(Action::PlaceBlock));
+
+        // still held by the key after letting go of the button
+        press_key(&mut app, KeyCode::Q);
+        app.world
+            .resource_mut::<Input<MouseButton>>()
+            .release(MouseButton::Right);
+        app.update();
+        assert!(actions(&app).pressed(Action::PlaceBlock));
+        assert!(!actions(&app).just_released(Action::PlaceBlock));
+
+        release_key(&mut app, KeyCode::Q);
+        assert!(actions(&app).just_released(Action::PlaceBlock));
+    }
+
+    #[test]
+    fn unbound_keys_do_nothing() {
+        let mut app = app("(bindings: { MoveForward: [Key(W)], BreakBlock: [] })");
+
+        press_key(&mut app, KeyCode::S);
+        app.world
+            .resource_mut::<Input<MouseButton>>()
+            .press(MouseButton::Left);
+        app.update();
+
+        assert_eq!(actions(&app).get_pressed().count(), 0);
+    }
+
+    #[test]
+    fn rebound_actions_follow_the_input_map() {
+        let mut app = app("// arrow keys instead of WASD
+            (
+                bindings: {
+                    MoveForward: [Key(Up)],
+                    HotbarSlot(2): [Key(Key1)],
+                    FlyUp: [Key(Space)],
+                    Jump: [Key(Space)],
+                },
+            )");
+
+        press_key(&mut app, KeyCode::W);
+        assert!(!actions(&app).pressed(Action::MoveForward));
+
+        press_key(&mut app, KeyCode::Up);
+        assert!(actions(&app).just_pressed(Action::MoveForward));
+
+        press_key(&mut app, KeyCode::Key1);
+        assert!(actions(&app).just_pressed(Action::HotbarSlot(2)));
+        assert!(!actions(&app).pressed(Action::HotbarSlot(0)));
+
+        // one key can trigger several actions
+        press_key(&mut app, KeyCode::Space);
+        assert!(actions(&app).just_pressed(Action::FlyUp));
+        assert!(actions(&app).just_pressed(Action::Jump));
+    }
+
+    #[test]
+    fn default_input_map_binds_every_hotbar_slot() {
+        let input_map: InputMap = ron::from_str(include_str!("../assets/input.ron")).unwrap();
+
+        for slot in 0..crate::inventory::HOTBAR_SLOTS {
+            assert!(input_map.bindings.contains_key(&Action::HotbarSlot(slot)));
+        }
+    }
+}
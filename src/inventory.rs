@@ -0,0 +1,383 @@
+use std::ops::Range;
+
+use bevy::prelude::*;
+
+use crate::block::BlockType;
+
+/// Most items a single slot holds.
+pub const MAX_STACK_SIZE: u32 = 64;
+/// Slots at the start of the inventory making up the hotbar, the rest is storage.
+pub const HOTBAR_SLOTS: usize = 9;
+pub const INVENTORY_SLOTS: usize = HOTBAR_SLOTS + 27;
+
+/// A number of blocks of the same kind, held in one slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect, FromReflect)]
+pub struct ItemStack {
+    pub block: BlockType,
+    /// Never `0`, an empty slot holds no stack at all.
+    pub count: u32,
+}
+
+impl ItemStack {
+    pub fn new(block: BlockType, count: u32) -> Self {
+        return Self { block, count };
+    }
+
+    /// How many more items fit onto this stack.
+    pub fn space(&self) -> u32 {
+        return MAX_STACK_SIZE.saturating_sub(self.count);
+    }
+
+    /// Puts as much of `other` onto this stack as fits, if they are the same block.
+    /// Returns what is left over.
+    pub fn merge(&mut self, other: ItemStack) -> Option<ItemStack> {
+        if other.block != self.block {
+            return Some(other);
+        }
+
+        let moved = other.count.min(self.space());
+        self.count += moved;
+
+        return ItemStack::with_count(other.block, other.count - moved);
+    }
+
+    /// A stack of `count` items, `None` if that's none at all.
+    fn with_count(block: BlockType, count: u32) -> Option<ItemStack> {
+        return (count > 0).then_some(ItemStack::new(block, count));
+    }
+}
+
+#[derive(Debug, Default, Reflect, FromReflect, Clone, PartialEq, Eq)]
+pub struct Slot {
+    pub contains: Option<ItemStack>,
+}
+
+impl Slot {
+    pub fn new() -> Self {
+        return Self { contains: None };
+    }
+}
+
+/// The blocks the player carries. Placing a block takes it out of the inventory,
+/// breaking one puts it in.
+#[derive(Resource, Debug, Reflect, FromReflect)]
+#[reflect(Resource)]
+pub struct Inventory {
+    pub items: Vec<Slot>,
+    pub selected: usize,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        return Self {
+            items: vec![Slot::new(); INVENTORY_SLOTS],
+            selected: 0,
+        };
+    }
+
+    /// Adds `count` items of a block, topping up existing stacks of it before
+    /// filling empty slots, both in slot order. Returns how many didn't fit.
+    pub fn add(&mut self, block: BlockType, count: u32) -> u32 {
+        let Some(stack) = ItemStack::with_count(block, count) else {
+            return 0;
+        };
+
+        return self
+            .insert(0..self.items.len(), stack)
+            .map_or(0, |stack| stack.count);
+    }
+
+    /// Puts a stack, which may be larger than [`MAX_STACK_SIZE`], into a range of slots.
+    /// Returns what didn't fit.
+    fn insert(&mut self, slots: Range<usize>, stack: ItemStack) -> Option<ItemStack> {
+        let mut remaining = Some(stack);
+
+        for slot in self.items[slots.clone()].iter_mut() {
+            if let (Some(stack), Some(adding)) = (slot.contains.as_mut(), remaining) {
+                remaining = stack.merge(adding);
+            }
+        }
+
+        for slot in self.items[slots].iter_mut() {
+            let Some(adding) = remaining else {
+                break;
+            };
+
+            if slot.contains.is_none() {
+                let moved = adding.count.min(MAX_STACK_SIZE);
+                slot.contains = Some(ItemStack::new(adding.block, moved));
+                remaining = ItemStack::with_count(adding.block, adding.count - moved);
+            }
+        }
+
+        return remaining;
+    }
+
+    /// Takes up to `count` items out of a slot, emptying it once none are left.
+    pub fn remove(&mut self, slot: usize, count: u32) -> Option<ItemStack> {
+        let contains = &mut self.items[slot].contains;
+        let stack = contains.as_mut()?;
+
+        let taken = count.min(stack.count);
+        stack.count -= taken;
+
+        let block = stack.block;
+        if stack.count == 0 {
+            *contains = None;
+        }
+
+        return ItemStack::with_count(block, taken);
+    }
+
+    /// Moves the selection along the hotbar by `offset` slots, wrapping around at its ends.
+    pub fn scroll_selection(&mut self, offset: i32) {
+        self.selected = (self.selected as i32 + offset).rem_euclid(HOTBAR_SLOTS as i32) as usize;
+    }
+
+    /// Puts a stack into a slot, merging it with the stack there if it is the same block
+    /// and swapping them otherwise. Returns what is left in hand.
+    pub fn put(&mut self, slot: usize, stack: ItemStack) -> Option<ItemStack> {
+        let contains = &mut self.items[slot].contains;
+
+        return match contains {
+            Some(existing) if existing.block == stack.block => existing.merge(stack),
+            _ => contains.replace(stack),
+        };
+    }
+
+    /// Moves a stack between the hotbar and the storage, onto stacks of the same block
+    /// first and into empty slots after.
+    pub fn quick_move(&mut self, slot: usize) {
+        let Some(moving) = self.items[slot].contains.take() else {
+            return;
+        };
+
+        let target = if slot < HOTBAR_SLOTS {
+            HOTBAR_SLOTS..self.items.len()
+        } else {
+            0..HOTBAR_SLOTS
+        };
+
+        self.items[slot].contains = self.insert(target, moving);
+    }
+
+    /// Takes the larger half out of a slot, leaving the smaller one behind.
+    pub fn split(&mut self, slot: usize) -> Option<ItemStack> {
+        let count = self.items[slot].contains?.count;
+
+        return self.remove(slot, count.div_ceil(2));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: BlockType = BlockType(0);
+    const SOIL: BlockType = BlockType(1);
+
+    fn stack(slot: &Slot) -> Option<(BlockType, u32)> {
+        return slot.contains.map(|stack| (stack.block, stack.count));
+    }
+
+    /// An inventory with every slot holding a full stack of stone.
+    fn full() -> Inventory {
+        let mut inventory = Inventory::new();
+        assert_eq!(
+            inventory.add(STONE, MAX_STACK_SIZE * INVENTORY_SLOTS as u32),
+            0
+        );
+
+        return inventory;
+    }
+
+    #[test]
+    fn merging_stacks() {
+        let mut onto = ItemStack::new(STONE, 60);
+        assert_eq!(
+            onto.merge(ItemStack::new(STONE, 10)),
+            Some(ItemStack::new(STONE, 6))
+        );
+        assert_eq!(onto.count, MAX_STACK_SIZE);
+
+        // a full stack takes nothing
+        assert_eq!(
+            onto.merge(ItemStack::new(STONE, 1)),
+            Some(ItemStack::new(STONE, 1))
+        );
+
+        let mut onto = ItemStack::new(STONE, 3);
+        assert_eq!(onto.merge(ItemStack::new(STONE, 4)), None);
+        assert_eq!(onto.count, 7);
+
+        // different blocks don't mix
+        assert_eq!(
+            onto.merge(ItemStack::new(SOIL, 4)),
+            Some(ItemStack::new(SOIL, 4))
+        );
+        assert_eq!(onto, ItemStack::new(STONE, 7));
+    }
+
+    #[test]
+    fn default_inventory_has_every_slot() {
+        let inventory = Inventory::default();
+
+        assert_eq!(inventory.items.len(), INVENTORY_SLOTS);
+        assert_eq!(inventory.items[inventory.selected].contains, None);
+    }
+
+    #[test]
+    fn add_tops_up_stacks_before_filling_empty_slots() {
+        let mut inventory = Inventory::new();
+        inventory.items[3].contains = Some(ItemStack::new(STONE, 60));
+        inventory.items[1].contains = Some(ItemStack::new(SOIL, 1));
+
+        assert_eq!(inventory.add(STONE, 10), 0);
+        assert_eq!(stack(&inventory.items[3]), Some((STONE, MAX_STACK_SIZE)));
+        assert_eq!(stack(&inventory.items[0]), Some((STONE, 6)));
+        assert_eq!(stack(&inventory.items[1]), Some((SOIL, 1)));
+
+        // more than a stack takes several slots
+        assert_eq!(inventory.add(SOIL, 2 * MAX_STACK_SIZE), 0);
+        assert_eq!(stack(&inventory.items[1]), Some((SOIL, MAX_STACK_SIZE)));
+        assert_eq!(stack(&inventory.items[2]), Some((SOIL, MAX_STACK_SIZE)));
+        assert_eq!(stack(&inventory.items[4]), Some((SOIL, 1)));
+
+        assert_eq!(inventory.add(SOIL, 0), 0);
+        assert_eq!(stack(&inventory.items[5]), None);
+    }
+
+    #[test]
+    fn add_to_a_full_inventory() {
+        let mut inventory = full();
+        assert_eq!(inventory.add(STONE, 5), 5);
+        assert_eq!(inventory.add(SOIL, 1), 1);
+
+        // whatever fits goes in, the rest is returned
+        inventory.remove(7, 3);
+        assert_eq!(inventory.add(STONE, 5), 2);
+        assert_eq!(stack(&inventory.items[7]), Some((STONE, MAX_STACK_SIZE)));
+    }
+
+    #[test]
+    fn remove_empties_the_slot() {
+        let mut inventory = Inventory::new();
+        inventory.add(STONE, 5);
+
+        assert_eq!(inventory.remove(0, 2), Some(ItemStack::new(STONE, 2)));
+        assert_eq!(stack(&inventory.items[0]), Some((STONE, 3)));
+
+        assert_eq!(inventory.remove(0, 10), Some(ItemStack::new(STONE, 3)));
+        assert_eq!(stack(&inventory.items[0]), None);
+
+        assert_eq!(inventory.remove(0, 1), None);
+        assert_eq!(inventory.remove(1, 0), None);
+    }
+
+    #[test]
+    fn put_merges_or_swaps() {
+        let mut inventory = Inventory::new();
+
+        assert_eq!(inventory.put(0, ItemStack::new(STONE, 40)), None);
+        assert_eq!(
+            inventory.put(0, ItemStack::new(STONE, 40)),
+            Some(ItemStack::new(STONE, 16))
+        );
+        assert_eq!(stack(&inventory.items[0]), Some((STONE, MAX_STACK_SIZE)));
+
+        // putting anything onto a full stack of the same block gives it back
+        assert_eq!(
+            inventory.put(0, ItemStack::new(STONE, 16)),
+            Some(ItemStack::new(STONE, 16))
+        );
+
+        assert_eq!(
+            inventory.put(0, ItemStack::new(SOIL, 3)),
+            Some(ItemStack::new(STONE, MAX_STACK_SIZE))
+        );
+        assert_eq!(stack(&inventory.items[0]), Some((SOIL, 3)));
+    }
+
+    #[test]
+    fn quick_move_between_hotbar_and_storage() {
+        let mut inventory = Inventory::new();
+        inventory.items[0].contains = Some(ItemStack::new(STONE, 10));
+        inventory.items[HOTBAR_SLOTS + 5].contains = Some(ItemStack::new(STONE, 60));
+
+        inventory.quick_move(0);
+        assert_eq!(stack(&inventory.items[0]), None);
+        assert_eq!(
+            stack(&inventory.items[HOTBAR_SLOTS + 5]),
+            Some((STONE, MAX_STACK_SIZE))
+        );
+        assert_eq!(stack(&inventory.items[HOTBAR_SLOTS]), Some((STONE, 6)));
+
+        inventory.quick_move(HOTBAR_SLOTS + 5);
+        assert_eq!(stack(&inventory.items[0]), Some((STONE, MAX_STACK_SIZE)));
+        assert_eq!(stack(&inventory.items[HOTBAR_SLOTS + 5]), None);
+
+        // an empty slot moves nothing
+        inventory.quick_move(1);
+        assert_eq!(stack(&inventory.items[1]), None);
+    }
+
+    #[test]
+    fn quick_move_into_a_full_hotbar() {
+        let mut inventory = Inventory::new();
+        for slot in 0..HOTBAR_SLOTS {
+            inventory.items[slot].contains = Some(ItemStack::new(SOIL, 1));
+        }
+        inventory.items[HOTBAR_SLOTS].contains = Some(ItemStack::new(STONE, 8));
+
+        // nothing fits, the stack stays where it was
+        inventory.quick_move(HOTBAR_SLOTS);
+        assert_eq!(stack(&inventory.items[HOTBAR_SLOTS]), Some((STONE, 8)));
+
+        // stacks of the same block take what they can
+        inventory.items[4].contains = Some(ItemStack::new(STONE, 61));
+        inventory.quick_move(HOTBAR_SLOTS);
+        assert_eq!(stack(&inventory.items[4]), Some((STONE, MAX_STACK_SIZE)));
+        assert_eq!(stack(&inventory.items[HOTBAR_SLOTS]), Some((STONE, 5)));
+    }
+
+    #[test]
+    fn split_takes_the_larger_half() {
+        let mut inventory = Inventory::new();
+        inventory.add(STONE, 7);
+
+        assert_eq!(inventory.split(0), Some(ItemStack::new(STONE, 4)));
+        assert_eq!(stack(&inventory.items[0]), Some((STONE, 3)));
+
+        assert_eq!(inventory.split(0), Some(ItemStack::new(STONE, 2)));
+        assert_eq!(inventory.split(0), Some(ItemStack::new(STONE, 1)));
+        assert_eq!(stack(&inventory.items[0]), None);
+        assert_eq!(inventory.split(0), None);
+
+        inventory.add(SOIL, MAX_STACK_SIZE);
+        assert_eq!(inventory.split(0), Some(ItemStack::new(SOIL, 32)));
+        assert_eq!(stack(&inventory.items[0]), Some((SOIL, 32)));
+    }
+
+    #[test]
+    fn scroll_selection_wraps_around_the_hotbar() {
+        let mut inventory = Inventory::new();
+
+        inventory.scroll_selection(1);
+        assert_eq!(inventory.selected, 1);
+
+        inventory.scroll_selection(-2);
+        assert_eq!(inventory.selected, HOTBAR_SLOTS - 1);
+
+        inventory.scroll_selection(1);
+        assert_eq!(inventory.selected, 0);
+
+        inventory.scroll_selection(HOTBAR_SLOTS as i32 * 3 + 2);
+        assert_eq!(inventory.selected, 2);
+    }
+}
@@ -1,17 +1,27 @@
-use std::f32::consts::PI;
+// explicit returns are the style of this crate
+#![allow(clippy::needless_return)]
 
-use bevy::{pbr::CascadeShadowConfig, prelude::*, window::CursorGrabMode};
-use bevy_aabb_instancing::VertexPullingRenderPlugin;
+use bevy::{prelude::*, window::CursorGrabMode};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_rapier3d::prelude::*;
-use chunk::Chunk;
 
-use crate::{block::*, chunk::initialize_example_chunk};
+use crate::{
+    block::*,
+    chunk::ChunkPlugin,
+    input::Action,
+    material::ChunkMaterial,
+    state::{GameState, GameStatePlugin},
+};
 
 mod block;
 mod camera;
 mod chunk;
 mod event;
+mod input;
+mod inventory;
+mod material;
+mod player;
+mod state;
 mod ui;
 mod util;
 
@@ -21,11 +31,19 @@ fn main() {
     let mut app = App::new();
 
     app.add_plugins(DefaultPlugins)
+        .add_plugin(GameStatePlugin)
+        .add_plugin(input::InputMapPlugin)
         .add_plugin(WorldInspectorPlugin::default())
         .add_plugin(camera::CameraControllerPlugin)
         .add_plugin(ui::UserInterfacePlugin)
         .add_plugin(event::EventSystemPlugin)
         .add_plugin(BlockPlugin)
+        .add_plugin(MaterialPlugin::<ChunkMaterial> {
+            prepass_enabled: false,
+            ..default()
+        })
+        .add_plugin(ChunkPlugin)
+        .add_plugin(player::PlayerPlugin)
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
         // .add_plugin(RapierDebugRenderPlugin {
         //     always_on_top: true,
@@ -33,52 +51,21 @@ fn main() {
         //     mode: DebugRenderMode::COLLIDER_SHAPES,
         //     ..default()
         // })
-        .add_plugin(VertexPullingRenderPlugin { outlines: true })
-        .add_startup_system(setup)
+        .add_system(setup.in_schedule(OnEnter(GameState::Loading)))
         .add_startup_system(print_resources)
-        .add_startup_system(initialize_example_chunk)
-        .add_system(cursor_grab_system)
-        .add_system(fixed.in_schedule(CoreSchedule::FixedUpdate))
-        .insert_resource(FixedTime::new_from_secs(5.0))
+        .add_system(cursor_grab_system.in_set(OnUpdate(GameState::InGame)))
+        // the fluid simulation steps at the fixed timestep
+        .insert_resource(FixedTime::new_from_secs(0.2))
         .insert_resource(ClearColor(Color::BLACK))
         .run();
 }
 
-fn setup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    // plane
-    commands
-        .spawn(PbrBundle {
-            mesh: meshes.add(shape::Plane::from_size(50.0).into()),
-            material: materials.add(Color::rgb(0.3, 0.5, 0.3).into()),
-            ..default()
-        })
-        .insert(Collider::cuboid(50.0, 0.0, 50.0));
-    // light
-    commands.spawn(DirectionalLightBundle {
-        directional_light: DirectionalLight {
-            illuminance: 25000.0,
-            color: Color::WHITE,
-            shadows_enabled: true,
-            ..default()
-        },
-        transform: Transform {
-            translation: Vec3::new(0.0, 2.0, 0.0),
-            rotation: Quat::from_rotation_x(-PI / 4.),
-            ..default()
-        },
-        cascade_shadow_config: CascadeShadowConfig { ..default() },
-        ..default()
+fn setup(mut commands: Commands) {
+    // chunks bake their own light into their meshes, everything else is lit evenly
+    commands.insert_resource(AmbientLight {
+        color: Color::WHITE,
+        brightness: 1.0,
     });
-
-    Chunk::get_some_noise();
-}
-
-fn fixed() {
-    println!("running fixed system")
 }
 
 fn print_resources(world: &World) {
@@ -98,18 +85,13 @@ fn print_resources(world: &World) {
 }
 
 // TODO capture cursor in screen and dont let it get out
-fn cursor_grab_system(mut windows: Query<&mut Window>, key: Res<Input<KeyCode>>) {
+fn cursor_grab_system(mut windows: Query<&mut Window>, actions: Res<Input<Action>>) {
     let mut window = windows.get_single_mut().unwrap();
 
-    if key.just_pressed(KeyCode::LControl) {
+    if actions.just_pressed(Action::GrabCursor) {
         window.cursor.grab_mode = CursorGrabMode::Locked;
         window.cursor.visible = false;
 
         println!("locking cursor");
     }
-
-    if key.just_pressed(KeyCode::Escape) {
-        window.cursor.grab_mode = CursorGrabMode::None;
-        window.cursor.visible = true;
-    }
 }
@@ -1,21 +1,58 @@
-impl Material for CustomMaterial {
+use bevy::{
+    pbr::{MaterialPipeline, MaterialPipelineKey},
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        mesh::{MeshVertexAttribute, MeshVertexBufferLayout},
+        render_resource::{
+            AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+            VertexFormat,
+        },
+    },
+};
+
+/// Area of the atlas tile a vertex samples from, as `(min.x, min.y, max.x, max.y)`.
+/// The regular UVs count blocks instead, so textures repeat across merged quads.
+pub const ATTRIBUTE_TILE_RECT: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_TileRect", 409_712_348, VertexFormat::Float32x4);
+
+impl Material for ChunkMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shader/chunk.wgsl".into()
+    }
+
     fn fragment_shader() -> ShaderRef {
-        "shaders/custom_material.wgsl".into()
+        "shader/chunk.wgsl".into()
     }
 
     fn alpha_mode(&self) -> AlphaMode {
         self.alpha_mode
     }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(1),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(2),
+            ATTRIBUTE_TILE_RECT.at_shader_location(3),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+
+        Ok(())
+    }
 }
 
 // This is the struct that will be passed to your shader
 #[derive(AsBindGroup, TypeUuid, Debug, Clone)]
 #[uuid = "f690fdae-d598-45ab-8225-97e2a3f056e0"]
-pub struct CustomMaterial {
-    #[uniform(0)]
-    color: Color,
-    #[texture(1)]
-    #[sampler(2)]
-    color_texture: Option<Handle<Image>>,
-    alpha_mode: AlphaMode,
+pub struct ChunkMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    pub atlas: Handle<Image>,
+    pub alpha_mode: AlphaMode,
 }
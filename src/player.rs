@@ -0,0 +1,510 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::{input::mouse::MouseMotion, prelude::*, transform::TransformSystem};
+use bevy_rapier3d::prelude::*;
+use smooth_bevy_cameras::{controllers::fps::FpsCameraController, LookTransform};
+
+use crate::{
+    block::BlockRegistry,
+    chunk::{
+        generation::{TerrainGenerator, SEA_LEVEL},
+        meshing::MeshingChunks,
+        ChunkRegistry, CHUNK_SIZE,
+    },
+    input::Action,
+    state::GameState,
+    ui::inventory_screen::inventory_screen_closed,
+};
+
+/// Half the height of the straight part of the player's capsule.
+const CAPSULE_HALF_HEIGHT: f32 = 0.6;
+/// The capsule is 1.5 blocks tall while crouching, so the player fits under slabs of 2 blocks.
+const CROUCHING_HALF_HEIGHT: f32 = 0.45;
+const CAPSULE_RADIUS: f32 = 0.3;
+/// Height of the eyes above the center of the capsule, 1.62 blocks above the feet.
+const EYE_OFFSET: f32 = 0.72;
+/// Height of the eyes above the center of the crouching capsule, 1.27 blocks above the feet.
+const CROUCHING_EYE_OFFSET: f32 = 0.52;
+/// Blocks of air the player needs above the ground to stand up in.
+const STANDING_ROOM: i32 = 2;
+/// Pitch stays a little short of straight up and down, where the yaw would flip.
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+pub struct PlayerPlugin;
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PlayerSettings::default())
+            .insert_resource(MovementMode::default())
+            .add_system(spawn_player.in_schedule(OnEnter(GameState::Loading)))
+            .add_system(finish_loading.in_set(OnUpdate(GameState::Loading)))
+            .add_system(toggle_movement_mode.in_set(OnUpdate(GameState::InGame)))
+            .add_systems(
+                (look_around, crouch, move_player)
+                    .chain()
+                    .after(toggle_movement_mode)
+                    .distributive_run_if(is_walking)
+                    // the mouse and keys work the inventory screen instead, shift quick moves there
+                    .distributive_run_if(inventory_screen_closed)
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            // after the physics wrote back the player's position, before it reaches the renderer
+            .add_system(
+                follow_player
+                    .in_base_set(CoreSet::PostUpdate)
+                    .before(TransformSystem::TransformPropagate)
+                    .run_if(is_walking),
+            );
+    }
+}
+
+/// How the player moves, in blocks and seconds.
+#[derive(Resource, Debug, Clone)]
+pub struct PlayerSettings {
+    pub walk_speed: f32,
+    pub sprint_speed: f32,
+    pub crouch_speed: f32,
+    /// Upward speed when jumping, enough to get on top of a block.
+    pub jump_speed: f32,
+    pub gravity: f32,
+    pub max_fall_speed: f32,
+    /// Highest ledge the player walks up onto without jumping.
+    pub step_height: f32,
+    /// Radians turned per pixel the mouse moved.
+    pub mouse_sensitivity: f32,
+}
+
+impl Default for PlayerSettings {
+    fn default() -> Self {
+        return Self {
+            walk_speed: 4.3,
+            sprint_speed: 7.0,
+            crouch_speed: 1.5,
+            jump_speed: 8.4,
+            gravity: 28.0,
+            max_fall_speed: 50.0,
+            step_height: 1.05,
+            mouse_sensitivity: 0.003,
+        };
+    }
+}
+
+/// Whether the camera follows the player walking around or flies freely,
+/// steered by its [`FpsCameraController`].
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MovementMode {
+    #[default]
+    Walking,
+    Flying,
+}
+
+#[derive(Component, Debug, Default)]
+pub struct Player {
+    /// Rotation around the vertical axis in radians, `0.0` facing towards negative z.
+    pub yaw: f32,
+    /// Rotation up (positive) or down (negative) from the horizon in radians.
+    pub pitch: f32,
+    pub vertical_velocity: f32,
+    pub crouching: bool,
+}
+
+impl Player {
+    fn eye_offset(&self) -> Vec3 {
+        if self.crouching {
+            return Vec3::Y * CROUCHING_EYE_OFFSET;
+        }
+
+        return Vec3::Y * EYE_OFFSET;
+    }
+
+    fn rotation(&self) -> Quat {
+        return Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, 0.0);
+    }
+}
+
+fn is_walking(movement_mode: Res<MovementMode>) -> bool {
+    return *movement_mode == MovementMode::Walking;
+}
+
+/// Center of the standing capsule of a player whose feet are on top of the block at `ground`.
+fn standing_center(ground: i32) -> f32 {
+    return ground as f32 + 1.0 + CAPSULE_HALF_HEIGHT + CAPSULE_RADIUS + 0.1;
+}
+
+/// Height of the topmost block in a world space column between `bottom` and `top` that
+/// can be stood on, with [`STANDING_ROOM`] blocks free of solid blocks and fluids above it.
+/// `None` if there is no such block or a chunk in between is not loaded.
+fn find_ground(
+    chunk_registry: &ChunkRegistry,
+    block_registry: &BlockRegistry,
+    x: i32,
+    z: i32,
+    bottom: i32,
+    top: i32,
+) -> Option<i32> {
+    let mut free = 0;
+
+    for y in (bottom..=top).rev() {
+        let position = IVec3::new(x, y, z);
+        let (chunk, _) = ChunkRegistry::world_to_chunk(position);
+        chunk_registry.get_chunk(chunk)?;
+
+        let occupied = chunk_registry
+            .get_block(position)
+            .is_some_and(|block_type| {
+                block_registry.is_solid(block_type) || block_registry.is_fluid(block_type)
+            });
+
+        if !occupied {
+            free += 1;
+        } else if free >= STANDING_ROOM {
+            return Some(y);
+        } else {
+            free = 0;
+        }
+    }
+
+    return None;
+}
+
+/// Spawns the player above the world origin, at the height the terrain is generated with.
+/// Once the chunks there are loaded, [`finish_loading`] puts the player on the actual ground.
+fn spawn_player(
+    mut commands: Commands,
+    settings: Res<PlayerSettings>,
+    terrain_generator: Res<TerrainGenerator>,
+) {
+    let center = standing_center(terrain_generator.height_at(0, 0).max(SEA_LEVEL));
+
+    commands.spawn((
+        Player::default(),
+        Name::new("Player"),
+        TransformBundle::from_transform(Transform::from_xyz(0.5, center, 0.5)),
+        RigidBody::KinematicPositionBased,
+        Collider::capsule_y(CAPSULE_HALF_HEIGHT, CAPSULE_RADIUS),
+        KinematicCharacterController {
+            offset: CharacterLength::Absolute(0.01),
+            autostep: Some(CharacterAutostep {
+                max_height: CharacterLength::Absolute(settings.step_height),
+                min_width: CharacterLength::Absolute(CAPSULE_RADIUS),
+                include_dynamic_bodies: false,
+            }),
+            snap_to_ground: Some(CharacterLength::Absolute(0.2)),
+            ..default()
+        },
+    ));
+}
+
+/// Switches between walking and flying. The camera takes off from the player's eyes
+/// and the player lands wherever the camera was when switching back.
+fn toggle_movement_mode(
+    actions: Res<Input<Action>>,
+    mut movement_mode: ResMut<MovementMode>,
+    mut players: Query<(&mut Player, &mut Transform), Without<FpsCameraController>>,
+    mut cameras: Query<(&mut FpsCameraController, &mut LookTransform, &Transform)>,
+) {
+    if !actions.just_pressed(Action::ToggleFlying) {
+        return;
+    }
+
+    let (Ok((mut player, mut player_transform)), Ok((mut controller, mut look, camera_transform))) =
+        (players.get_single_mut(), cameras.get_single_mut())
+    else {
+        return;
+    };
+
+    match *movement_mode {
+        MovementMode::Walking => {
+            look.eye = camera_transform.translation;
+            look.target = camera_transform.translation + camera_transform.forward();
+            controller.enabled = true;
+
+            *movement_mode = MovementMode::Flying;
+        }
+        MovementMode::Flying => {
+            let forward = camera_transform.forward();
+            player.yaw = (-forward.x).atan2(-forward.z);
+            player.pitch = forward.y.asin().clamp(-MAX_PITCH, MAX_PITCH);
+            player.vertical_velocity = 0.0;
+            player_transform.translation = camera_transform.translation - player.eye_offset();
+            controller.enabled = false;
+
+            *movement_mode = MovementMode::Walking;
+        }
+    }
+
+    println!("Switched to {:?}", *movement_mode);
+}
+
+fn look_around(
+    mut mouse_motion: EventReader<MouseMotion>,
+    settings: Res<PlayerSettings>,
+    mut players: Query<&mut Player>,
+) {
+    let delta: Vec2 = mouse_motion.iter().map(|motion| motion.delta).sum();
+
+    let Ok(mut player) = players.get_single_mut() else {
+        return;
+    };
+
+    if delta == Vec2::ZERO {
+        return;
+    }
+
+    player.yaw -= delta.x * settings.mouse_sensitivity;
+    player.pitch =
+        (player.pitch - delta.y * settings.mouse_sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+}
+
+/// Whether the terrain of a chunk can be walked on, meaning it is loaded and has
+/// its collider, unless it turned out to be empty.
+fn has_terrain(
+    chunk_registry: &ChunkRegistry,
+    meshing_chunks: &MeshingChunks,
+    position: IVec3,
+) -> bool {
+    let Some(chunk) = chunk_registry.get_chunk(position) else {
+        return false;
+    };
+
+    return chunk.entities.is_some()
+        || !(chunk_registry.dirty.contains(&position) || meshing_chunks.0.contains_key(&position));
+}
+
+/// Puts the player on the first ground with room to stand in the column below the spawn point,
+/// looking a chunk above and below it, and starts the game once that ground has its collider.
+fn finish_loading(
+    chunk_registry: Res<ChunkRegistry>,
+    block_registry: Res<BlockRegistry>,
+    meshing_chunks: Res<MeshingChunks>,
+    terrain_generator: Res<TerrainGenerator>,
+    mut players: Query<&mut Transform, With<Player>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Ok(mut transform) = players.get_single_mut() else {
+        return;
+    };
+
+    let estimate = terrain_generator.height_at(0, 0).max(SEA_LEVEL);
+    let Some(ground) = find_ground(
+        &chunk_registry,
+        &block_registry,
+        0,
+        0,
+        estimate - CHUNK_SIZE as i32,
+        estimate + CHUNK_SIZE as i32,
+    ) else {
+        return;
+    };
+
+    transform.translation = Vec3::new(0.5, standing_center(ground), 0.5);
+
+    let (chunk, _) = ChunkRegistry::world_to_chunk(IVec3::new(0, ground + 1, 0));
+
+    if has_terrain(&chunk_registry, &meshing_chunks, chunk)
+        && has_terrain(&chunk_registry, &meshing_chunks, chunk - IVec3::Y)
+    {
+        next_state.set(GameState::InGame);
+    }
+}
+
+/// Shrinks the player's capsule while crouching, keeping the feet where they are.
+/// The player only stands up again where there is room for the full capsule.
+fn crouch(
+    actions: Res<Input<Action>>,
+    rapier_context: Res<RapierContext>,
+    mut players: Query<(Entity, &mut Player, &mut Transform, &mut Collider)>,
+) {
+    let Ok((entity, mut player, mut transform, mut collider)) = players.get_single_mut() else {
+        return;
+    };
+
+    let crouching = actions.pressed(Action::Crouch);
+    if crouching == player.crouching {
+        return;
+    }
+
+    let shift = Vec3::Y * (CAPSULE_HALF_HEIGHT - CROUCHING_HALF_HEIGHT);
+
+    if crouching {
+        *collider = Collider::capsule_y(CROUCHING_HALF_HEIGHT, CAPSULE_RADIUS);
+        transform.translation -= shift;
+    } else {
+        let standing = Collider::capsule_y(CAPSULE_HALF_HEIGHT, CAPSULE_RADIUS);
+        let center = transform.translation + shift;
+        let filter = QueryFilter::default()
+            .exclude_sensors()
+            .exclude_collider(entity);
+
+        if rapier_context
+            .intersection_with_shape(center, Quat::IDENTITY, &standing, filter)
+            .is_some()
+        {
+            return;
+        }
+
+        *collider = standing;
+        transform.translation = center;
+    }
+
+    player.crouching = crouching;
+}
+
+/// Walks, sprints, crouches and jumps the player around, pulled down by gravity.
+fn move_player(
+    time: Res<Time>,
+    actions: Res<Input<Action>>,
+    settings: Res<PlayerSettings>,
+    chunk_registry: Res<ChunkRegistry>,
+    meshing_chunks: Res<MeshingChunks>,
+    mut players: Query<(
+        &mut Player,
+        &Transform,
+        &mut KinematicCharacterController,
+        Option<&KinematicCharacterControllerOutput>,
+    )>,
+) {
+    let Ok((mut player, transform, mut controller, output)) = players.get_single_mut() else {
+        return;
+    };
+
+    // don't fall through terrain that isn't there yet
+    let (chunk, _) = ChunkRegistry::world_to_chunk(transform.translation.floor().as_ivec3());
+    if !has_terrain(&chunk_registry, &meshing_chunks, chunk)
+        || !has_terrain(&chunk_registry, &meshing_chunks, chunk - IVec3::Y)
+    {
+        return;
+    }
+
+    let delta = time.delta_seconds();
+    let grounded = output.is_some_and(|output| output.grounded);
+
+    if let Some(output) = output {
+        // stop falling once on the ground and stop rising when hitting a ceiling
+        let blocked_above = output.desired_translation.y > 0.0
+            && output.effective_translation.y < output.desired_translation.y * 0.5;
+
+        if (grounded && player.vertical_velocity < 0.0) || blocked_above {
+            player.vertical_velocity = 0.0;
+        }
+    }
+
+    let speed = if player.crouching {
+        settings.crouch_speed
+    } else if actions.pressed(Action::Sprint) {
+        settings.sprint_speed
+    } else {
+        settings.walk_speed
+    };
+
+    let forward = Vec3::new(-player.yaw.sin(), 0.0, -player.yaw.cos());
+    let right = Vec3::new(player.yaw.cos(), 0.0, -player.yaw.sin());
+    let mut direction = Vec3::ZERO;
+
+    for (action, towards) in [
+        (Action::MoveForward, forward),
+        (Action::MoveBack, -forward),
+        (Action::MoveLeft, -right),
+        (Action::MoveRight, right),
+    ] {
+        if actions.pressed(action) {
+            direction += towards;
+        }
+    }
+
+    if grounded && actions.pressed(Action::Jump) {
+        player.vertical_velocity = settings.jump_speed;
+    }
+
+    player.vertical_velocity =
+        (player.vertical_velocity - settings.gravity * delta).max(-settings.max_fall_speed);
+
+    controller.translation =
+        Some((direction.normalize_or_zero() * speed + Vec3::Y * player.vertical_velocity) * delta);
+}
+
+/// Puts the camera at the player's eyes.
+fn follow_player(
+    players: Query<(&Player, &Transform), Without<FpsCameraController>>,
+    mut cameras: Query<&mut Transform, With<FpsCameraController>>,
+) {
+    let (Ok((player, player_transform)), Ok(mut camera_transform)) =
+        (players.get_single(), cameras.get_single_mut())
+    else {
+        return;
+    };
+
+    camera_transform.translation = player_transform.translation + player.eye_offset();
+    camera_transform.rotation = player.rotation();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    /// A loaded chunk at the origin with the given blocks in the column at `x = 0, z = 0`.
+    fn column(blocks: &[(u32, &str)]) -> (ChunkRegistry, BlockRegistry) {
+        let block_registry = BlockRegistry::from_assets();
+        let mut chunk = Chunk::empty(IVec3::ZERO);
+        for (y, id) in blocks {
+            chunk.set_block(UVec3::new(0, *y, 0), block_registry.by_id(id));
+        }
+
+        let mut chunk_registry = ChunkRegistry::default();
+        chunk_registry.insert(chunk);
+
+        return (chunk_registry, block_registry);
+    }
+
+    #[test]
+    fn finds_the_topmost_ground_with_room_above() {
+        let (chunk_registry, block_registry) = column(&[(2, "stone"), (5, "stone"), (6, "grass")]);
+
+        assert_eq!(
+            find_ground(&chunk_registry, &block_registry, 0, 0, 0, 15),
+            Some(6)
+        );
+    }
+
+    #[test]
+    fn skips_gaps_too_low_to_stand_in() {
+        // gaps one block high above the ground at 1
+        let (chunk_registry, block_registry) =
+            column(&[(1, "stone"), (4, "stone"), (6, "stone"), (15, "stone")]);
+
+        assert_eq!(
+            find_ground(&chunk_registry, &block_registry, 0, 0, 0, 15),
+            Some(6)
+        );
+        assert_eq!(
+            find_ground(&chunk_registry, &block_registry, 0, 0, 0, 7),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn water_is_not_room_to_stand_in() {
+        let (chunk_registry, block_registry) =
+            column(&[(2, "sand"), (3, "water"), (4, "water"), (5, "water")]);
+
+        assert_eq!(
+            find_ground(&chunk_registry, &block_registry, 0, 0, 0, 15),
+            Some(5)
+        );
+    }
+
+    #[test]
+    fn waits_for_unloaded_chunks() {
+        let (chunk_registry, block_registry) = column(&[(2, "stone")]);
+
+        assert_eq!(
+            find_ground(&chunk_registry, &block_registry, 0, 0, -16, 31),
+            None
+        );
+        assert_eq!(
+            find_ground(&chunk_registry, &block_registry, 0, 0, 0, 15),
+            Some(2)
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use bevy::{prelude::*, window::CursorGrabMode};
+
+use crate::{input::Action, ui::inventory_screen::InventoryScreen};
+
+/// Where the game is at. The world is only generated once a game is started
+/// and only changes while it is being played.
+#[derive(States, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameState {
+    #[default]
+    MainMenu,
+    /// Chunks around the player are being generated, the player waits until there is
+    /// ground to stand on.
+    Loading,
+    InGame,
+    Paused,
+}
+
+pub struct GameStatePlugin;
+
+impl Plugin for GameStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<GameState>()
+            .add_system(grab_cursor.in_schedule(OnEnter(GameState::InGame)))
+            .add_system(release_cursor.in_schedule(OnEnter(GameState::Paused)))
+            .add_system(
+                toggle_pause
+                    .run_if(in_state(GameState::InGame).or_else(in_state(GameState::Paused))),
+            );
+    }
+}
+
+/// Whether the world exists, which it does from loading on.
+pub fn world_exists(state: Res<State<GameState>>) -> bool {
+    return state.0 != GameState::MainMenu;
+}
+
+fn set_cursor_grabbed(windows: &mut Query<&mut Window>, grabbed: bool) {
+    let Ok(mut window) = windows.get_single_mut() else {
+        return;
+    };
+
+    window.cursor.grab_mode = if grabbed {
+        CursorGrabMode::Locked
+    } else {
+        CursorGrabMode::None
+    };
+    window.cursor.visible = !grabbed;
+}
+
+/// Grabs the cursor when playing starts or resumes, unless it is needed on the inventory screen.
+fn grab_cursor(mut windows: Query<&mut Window>, inventory_screen: Res<InventoryScreen>) {
+    set_cursor_grabbed(&mut windows, !inventory_screen.open);
+}
+
+/// Frees the cursor for the buttons of the pause menu.
+fn release_cursor(mut windows: Query<&mut Window>) {
+    set_cursor_grabbed(&mut windows, false);
+}
+
+fn toggle_pause(
+    actions: Res<Input<Action>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !actions.just_pressed(Action::Pause) {
+        return;
+    }
+
+    match state.0 {
+        GameState::InGame => next_state.set(GameState::Paused),
+        GameState::Paused => next_state.set(GameState::InGame),
+        _ => {}
+    }
+}
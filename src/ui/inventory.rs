@@ -1,71 +1,48 @@
-use bevy::prelude::*;
+use bevy::{input::mouse::MouseWheel, prelude::*};
 
-use crate::block::{BlockMaterialStore, BlockType};
+use crate::{
+    block::{BlockRegistry, BlockType},
+    input::Action,
+    inventory::{Inventory, ItemStack, Slot, HOTBAR_SLOTS, MAX_STACK_SIZE},
+    state::GameState,
+};
 
-const INVENTORY_OVERLAY_SLOTS: usize = 9;
-const SLOT_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
-const SLOT_SELECTED_COLOR: Color = Color::rgb(0.35, 0.35, 0.35);
+const INVENTORY_OVERLAY_SLOTS: usize = HOTBAR_SLOTS;
+pub const SLOT_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+pub const SLOT_SELECTED_COLOR: Color = Color::rgb(0.35, 0.35, 0.35);
 
 pub struct InventorySystemPlugin;
 
 impl Plugin for InventorySystemPlugin {
     fn build(&self, app: &mut App) {
         app.add_startup_system(initialize_inventory_overlay)
-            .add_system(update_inventory_overlay)
-            .add_event::<SelectInventorySlotEvent>()
+            .add_system(select_hotbar_slot.in_set(OnUpdate(GameState::InGame)))
+            .add_system(update_inventory_overlay.after(select_hotbar_slot))
+            .add_system(update_inventory_icons)
             .insert_resource(Inventory::new())
             .register_type::<Inventory>()
-            .register_type::<Slot>();
+            .register_type::<Slot>()
+            .register_type::<ItemStack>();
     }
 }
 
+/// A slot of the hotbar overlay, by its index in the [`Inventory`].
 #[derive(Component)]
-struct InventorySlotComponent;
+struct InventorySlotComponent(usize);
 
-#[derive(Resource, Debug, Reflect, FromReflect, Clone)]
-pub struct Slot {
-    contains: Option<Color>,
-}
-
-impl Slot {
-    pub fn new() -> Self {
-        return Self { contains: None };
-    }
-}
-
-#[derive(Resource, Debug, Reflect, FromReflect, Default)]
-#[reflect(Resource)]
-pub struct Inventory {
-    pub items: Vec<Slot>,
-    pub selected: usize,
-}
-
-impl Inventory {
-    pub fn new() -> Self {
-        return Self {
-            items: vec![Slot::new(); INVENTORY_OVERLAY_SLOTS],
-            selected: 0,
-        };
-    }
-
-    pub fn get_selected(&mut self) -> &mut Slot {
-        return &mut self.items[self.selected];
-    }
-
-    pub fn get_selected_with_index(&mut self) -> (usize, &mut Slot) {
-        return (self.selected, &mut self.items[self.selected]);
-    }
-}
+/// The coloured square of a slot, standing in for the block it holds.
+#[derive(Component)]
+pub struct InventorySlotIcon(pub usize);
 
-#[derive(Debug)]
-pub struct SelectInventorySlotEvent(pub usize);
+/// Text showing how many items a slot holds, left empty for single items.
+#[derive(Component)]
+pub struct InventorySlotCount(pub usize);
 
 pub fn initialize_inventory_overlay(
     mut commands: Commands,
     mut inventory: ResMut<Inventory>,
-    block_materials: ResMut<BlockMaterialStore>,
+    block_registry: Res<BlockRegistry>,
     asset_server: Res<AssetServer>,
-    materials: Res<Assets<StandardMaterial>>,
 ) {
     commands
         // main container
@@ -100,19 +77,10 @@ pub fn initialize_inventory_overlay(
                 })
                 .with_children(|overlay| {
                     for slot in 0..INVENTORY_OVERLAY_SLOTS {
-                        let maybe_handle = match slot {
-                            0 => Some(block_materials.data.get(&BlockType::Stone).unwrap()),
-                            1 => Some(block_materials.data.get(&BlockType::Soil).unwrap()),
-                            2 => Some(block_materials.data.get(&BlockType::Grass).unwrap()),
-                            _ => None,
-                        };
-
-                        let mut display_color = Color::rgba(0.0, 0.0, 0.0, 0.0);
-
-                        if let Some(material_handle) = maybe_handle {
-                            let color = materials.get(material_handle).unwrap().base_color;
-                            inventory.items[slot].contains = Some(color);
-                            display_color = color;
+                        // fill the slots with a stack of each registered block, in order
+                        if slot < block_registry.len() {
+                            inventory.items[slot].contains =
+                                Some(ItemStack::new(BlockType(slot as u16), MAX_STACK_SIZE));
                         }
 
                         overlay
@@ -130,16 +98,39 @@ pub fn initialize_inventory_overlay(
                                 ..default()
                             })
                             .with_children(|slot_rectangle| {
-                                slot_rectangle.spawn(NodeBundle {
-                                    style: Style {
-                                        margin: UiRect::all(Val::Auto),
-                                        size: Size::all(Val::Px(35.0)),
-                                        position_type: PositionType::Absolute,
+                                slot_rectangle.spawn((
+                                    NodeBundle {
+                                        style: Style {
+                                            margin: UiRect::all(Val::Auto),
+                                            size: Size::all(Val::Px(35.0)),
+                                            position_type: PositionType::Absolute,
+                                            ..default()
+                                        },
                                         ..default()
                                     },
-                                    background_color: BackgroundColor::from(display_color),
-                                    ..default()
-                                });
+                                    InventorySlotIcon(slot),
+                                ));
+
+                                slot_rectangle.spawn((
+                                    TextBundle::from_section(
+                                        "",
+                                        TextStyle {
+                                            font: asset_server.load("font/TiltWarp-Regular.ttf"),
+                                            font_size: 14.0,
+                                            color: Color::WHITE,
+                                        },
+                                    )
+                                    .with_style(Style {
+                                        position_type: PositionType::Absolute,
+                                        position: UiRect {
+                                            top: Val::Px(2.0),
+                                            left: Val::Px(6.0),
+                                            ..default()
+                                        },
+                                        ..default()
+                                    }),
+                                    InventorySlotCount(slot),
+                                ));
 
                                 // slot numbering
                                 slot_rectangle.spawn(
@@ -163,27 +154,76 @@ pub fn initialize_inventory_overlay(
                                     }),
                                 );
                             })
-                            .insert(InventorySlotComponent);
+                            .insert(InventorySlotComponent(slot));
                     }
-
-                    println!("{:?}", inventory);
                 });
         });
 }
 
+/// Selects a hotbar slot by its [`Action::HotbarSlot`], or the next or previous one
+/// with the scroll wheel.
+fn select_hotbar_slot(
+    actions: Res<Input<Action>>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    mut inventory: ResMut<Inventory>,
+) {
+    for slot in 0..HOTBAR_SLOTS {
+        if actions.just_pressed(Action::HotbarSlot(slot)) {
+            inventory.selected = slot;
+        }
+    }
+
+    let scrolled: f32 = mouse_wheel.iter().map(|wheel| wheel.y).sum();
+
+    // scrolling up moves towards the first slot
+    if scrolled != 0.0 {
+        inventory.scroll_selection(-scrolled.signum() as i32);
+    }
+}
+
+/// Highlights the selected hotbar slot.
 fn update_inventory_overlay(
-    mut query: Query<&mut BackgroundColor, With<InventorySlotComponent>>,
-    mut selected_inventory_slot: EventReader<SelectInventorySlotEvent>,
+    inventory: Res<Inventory>,
+    mut query: Query<(&InventorySlotComponent, &mut BackgroundColor)>,
 ) {
-    for event in selected_inventory_slot.iter() {
-        let selected_slot = event.0;
-
-        for (index, mut background_color) in query.iter_mut().enumerate() {
-            if index == selected_slot {
-                background_color.0 = SLOT_SELECTED_COLOR;
-            } else {
-                background_color.0 = SLOT_COLOR;
-            }
+    if !inventory.is_changed() {
+        return;
+    }
+
+    for (slot, mut background_color) in query.iter_mut() {
+        if slot.0 == inventory.selected {
+            background_color.0 = SLOT_SELECTED_COLOR;
+        } else {
+            background_color.0 = SLOT_COLOR;
         }
     }
 }
+
+/// Colours the slot icons after the blocks in the slots and updates the counts,
+/// whenever the inventory changed.
+fn update_inventory_icons(
+    inventory: Res<Inventory>,
+    block_registry: Res<BlockRegistry>,
+    mut icons: Query<(&InventorySlotIcon, &mut BackgroundColor)>,
+    mut counts: Query<(&InventorySlotCount, &mut Text)>,
+) {
+    if !inventory.is_changed() {
+        return;
+    }
+
+    let stack = |slot: usize| inventory.items.get(slot).and_then(|slot| slot.contains);
+
+    for (icon, mut background_color) in icons.iter_mut() {
+        background_color.0 = match stack(icon.0) {
+            Some(stack) => block_registry.get(stack.block).color(),
+            None => Color::rgba(0.0, 0.0, 0.0, 0.0),
+        };
+    }
+
+    for (count, mut text) in counts.iter_mut() {
+        text.sections[0].value = match stack(count.0) {
+            Some(stack) if stack.count > 1 => stack.count.to_string(),
+            _ => String::new(),
+        };
+    }
+}
@@ -0,0 +1,361 @@
+use bevy::{prelude::*, window::CursorGrabMode};
+
+use super::inventory::{InventorySlotCount, InventorySlotIcon, SLOT_COLOR, SLOT_SELECTED_COLOR};
+use crate::{
+    block::BlockRegistry,
+    input::Action,
+    inventory::{Inventory, ItemStack, HOTBAR_SLOTS, INVENTORY_SLOTS},
+    state::GameState,
+};
+
+const SLOT_SIZE: f32 = 60.0;
+const ICON_SIZE: f32 = 35.0;
+
+pub struct InventoryScreenPlugin;
+
+impl Plugin for InventoryScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InventoryScreen::default())
+            .add_startup_system(initialize_inventory_screen)
+            .add_system(toggle_inventory_screen.in_set(OnUpdate(GameState::InGame)))
+            .add_systems(
+                (
+                    click_inventory_slots,
+                    highlight_hovered_slots,
+                    update_held_stack_icon,
+                )
+                    .after(toggle_inventory_screen)
+                    .distributive_run_if(inventory_screen_open)
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
+
+/// State of the full inventory grid, storage above and the hotbar below,
+/// where stacks are moved around with the mouse.
+#[derive(Resource, Debug, Default)]
+pub struct InventoryScreen {
+    pub open: bool,
+    /// Stack picked up with the mouse, following the cursor.
+    pub held: Option<ItemStack>,
+    /// Where the held stack goes back to if the screen is closed while holding it.
+    held_from: Option<usize>,
+    /// Whether the held stack is being dragged, in which case letting go of the button
+    /// over another slot drops it there.
+    dragging: bool,
+}
+
+pub fn inventory_screen_open(screen: Res<InventoryScreen>) -> bool {
+    return screen.open;
+}
+
+pub fn inventory_screen_closed(screen: Res<InventoryScreen>) -> bool {
+    return !screen.open;
+}
+
+#[derive(Component)]
+struct InventoryScreenRoot;
+
+#[derive(Component)]
+struct InventoryScreenSlot(usize);
+
+/// The icon of the held stack, drawn at the cursor.
+#[derive(Component)]
+struct HeldStackIcon;
+
+fn initialize_inventory_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("font/TiltWarp-Regular.ttf");
+
+    let spawn_row = |parent: &mut ChildBuilder, slots: std::ops::Range<usize>| {
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    ..default()
+                },
+                ..default()
+            })
+            .with_children(|row| {
+                for slot in slots {
+                    row.spawn((
+                        ButtonBundle {
+                            style: Style {
+                                size: Size::all(Val::Px(SLOT_SIZE)),
+                                margin: UiRect::all(Val::Px(1.5)),
+                                ..default()
+                            },
+                            background_color: SLOT_COLOR.into(),
+                            ..default()
+                        },
+                        InventoryScreenSlot(slot),
+                    ))
+                    .with_children(|slot_rectangle| {
+                        slot_rectangle.spawn((
+                            NodeBundle {
+                                style: Style {
+                                    margin: UiRect::all(Val::Auto),
+                                    size: Size::all(Val::Px(ICON_SIZE)),
+                                    position_type: PositionType::Absolute,
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                            InventorySlotIcon(slot),
+                        ));
+
+                        slot_rectangle.spawn((
+                            TextBundle::from_section(
+                                "",
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size: 14.0,
+                                    color: Color::WHITE,
+                                },
+                            )
+                            .with_style(Style {
+                                position_type: PositionType::Absolute,
+                                position: UiRect {
+                                    bottom: Val::Px(2.0),
+                                    right: Val::Px(6.0),
+                                    ..default()
+                                },
+                                ..default()
+                            }),
+                            InventorySlotCount(slot),
+                        ));
+                    });
+                }
+            });
+    };
+
+    commands
+        // dims the world behind the grid
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    position_type: PositionType::Absolute,
+                    size: Size::all(Val::Percent(100.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.5).into(),
+                z_index: ZIndex::Global(1),
+                ..default()
+            },
+            InventoryScreenRoot,
+            Name::new("Inventory screen"),
+        ))
+        .with_children(|container| {
+            container
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        padding: UiRect::all(Val::Px(3.0)),
+                        ..default()
+                    },
+                    background_color: Color::rgb(0.5, 0.5, 0.5).into(),
+                    ..default()
+                })
+                .with_children(|grid| {
+                    // storage rows on top, the hotbar at the bottom like on screen
+                    for row in (HOTBAR_SLOTS..INVENTORY_SLOTS).step_by(HOTBAR_SLOTS) {
+                        spawn_row(grid, row..row + HOTBAR_SLOTS);
+                    }
+
+                    grid.spawn(NodeBundle {
+                        style: Style {
+                            size: Size::height(Val::Px(12.0)),
+                            ..default()
+                        },
+                        ..default()
+                    });
+
+                    spawn_row(grid, 0..HOTBAR_SLOTS);
+                });
+
+            container.spawn((
+                NodeBundle {
+                    style: Style {
+                        display: Display::None,
+                        position_type: PositionType::Absolute,
+                        size: Size::all(Val::Px(ICON_SIZE)),
+                        ..default()
+                    },
+                    z_index: ZIndex::Global(2),
+                    ..default()
+                },
+                HeldStackIcon,
+            ));
+        });
+}
+
+/// Opens and closes the inventory screen. The cursor is released while it is open
+/// and grabbed again once it closes, when a held stack is put back.
+/// The screen stays open while there is no room for the held stack.
+fn toggle_inventory_screen(
+    actions: Res<Input<Action>>,
+    mut screen: ResMut<InventoryScreen>,
+    mut inventory: ResMut<Inventory>,
+    mut windows: Query<&mut Window>,
+    mut roots: Query<&mut Style, With<InventoryScreenRoot>>,
+) {
+    if !actions.just_pressed(Action::ToggleInventory) {
+        return;
+    }
+
+    if screen.open {
+        if let Some(held) = screen.held.take() {
+            let left_over = match screen.held_from {
+                Some(slot) if inventory.items[slot].contains.is_none() => inventory.put(slot, held),
+                _ => Some(held),
+            };
+
+            if let Some(left_over) = left_over {
+                let remaining = inventory.add(left_over.block, left_over.count);
+
+                if remaining > 0 {
+                    screen.held = Some(ItemStack::new(left_over.block, remaining));
+                    println!("Inventory is full, put the held items down before closing it");
+                    return;
+                }
+            }
+        }
+
+        screen.dragging = false;
+        screen.held_from = None;
+    }
+
+    screen.open = !screen.open;
+
+    if let Ok(mut style) = roots.get_single_mut() {
+        style.display = if screen.open {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+
+    if let Ok(mut window) = windows.get_single_mut() {
+        window.cursor.grab_mode = if screen.open {
+            CursorGrabMode::None
+        } else {
+            CursorGrabMode::Locked
+        };
+        window.cursor.visible = screen.open;
+    }
+}
+
+/// Moves stacks around with the mouse:
+/// - [`Action::PickStack`] picks a stack up or puts the held one down, merging or swapping it,
+///   dragging it onto another slot and letting go does the same,
+/// - together with [`Action::QuickMove`] it moves a stack between the hotbar and the storage,
+/// - [`Action::SplitStack`] picks up half of a stack, or puts down a single item of the held one.
+fn click_inventory_slots(
+    actions: Res<Input<Action>>,
+    mut screen: ResMut<InventoryScreen>,
+    mut inventory: ResMut<Inventory>,
+    slots: Query<(&Interaction, &InventoryScreenSlot)>,
+) {
+    let hovered = slots
+        .iter()
+        .find(|(interaction, _)| **interaction != Interaction::None)
+        .map(|(_, slot)| slot.0);
+
+    if actions.just_released(Action::PickStack) {
+        let dragged_from = screen.held_from.filter(|_| screen.dragging);
+        screen.dragging = false;
+
+        if let (Some(held), Some(slot), Some(from)) = (screen.held, hovered, dragged_from) {
+            if slot != from {
+                screen.held = inventory.put(slot, held);
+                screen.held_from = Some(slot);
+            }
+        }
+    }
+
+    let Some(slot) = hovered else {
+        return;
+    };
+
+    if actions.just_pressed(Action::PickStack) {
+        let quick_move = actions.pressed(Action::QuickMove);
+
+        match screen.held {
+            None if quick_move => inventory.quick_move(slot),
+            None => {
+                screen.held = inventory.items[slot].contains.take();
+                screen.dragging = screen.held.is_some();
+            }
+            Some(held) => screen.held = inventory.put(slot, held),
+        }
+
+        screen.held_from = Some(slot);
+    }
+
+    if actions.just_pressed(Action::SplitStack) {
+        match screen.held {
+            None => screen.held = inventory.split(slot),
+            Some(held) => {
+                let fits = inventory.items[slot]
+                    .contains
+                    .is_none_or(|stack| stack.block == held.block && stack.space() > 0);
+
+                if fits {
+                    inventory.put(slot, ItemStack::new(held.block, 1));
+                    screen.held =
+                        (held.count > 1).then_some(ItemStack::new(held.block, held.count - 1));
+                }
+            }
+        }
+
+        screen.held_from = Some(slot);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn highlight_hovered_slots(
+    mut slots: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<InventoryScreenSlot>),
+    >,
+) {
+    for (interaction, mut background_color) in slots.iter_mut() {
+        background_color.0 = match interaction {
+            Interaction::None => SLOT_COLOR,
+            _ => SLOT_SELECTED_COLOR,
+        };
+    }
+}
+
+/// Draws the held stack at the cursor.
+fn update_held_stack_icon(
+    screen: Res<InventoryScreen>,
+    block_registry: Res<BlockRegistry>,
+    windows: Query<&Window>,
+    mut icons: Query<(&mut Style, &mut BackgroundColor), With<HeldStackIcon>>,
+) {
+    let Ok((mut style, mut background_color)) = icons.get_single_mut() else {
+        return;
+    };
+
+    let cursor = windows
+        .get_single()
+        .ok()
+        .and_then(|window| Some((window.cursor_position()?, window.height())));
+
+    let (Some(held), Some((cursor, height))) = (screen.held, cursor) else {
+        style.display = Display::None;
+        return;
+    };
+
+    // the cursor position starts at the bottom left of the window, ui positions at the top left
+    style.display = Display::Flex;
+    style.position = UiRect {
+        left: Val::Px(cursor.x - ICON_SIZE / 2.0),
+        top: Val::Px(height - cursor.y - ICON_SIZE / 2.0),
+        ..default()
+    };
+    background_color.0 = block_registry.get(held.block).color();
+}
@@ -0,0 +1,249 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{input::InputMap, state::GameState};
+
+const BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const BUTTON_HOVERED_COLOR: Color = Color::rgb(0.35, 0.35, 0.35);
+/// Covers the world entirely, it isn't there yet or shouldn't be seen.
+const OPAQUE_BACKGROUND: Color = Color::rgb(0.1, 0.1, 0.1);
+/// Lets the paused world show through.
+const PAUSED_BACKGROUND: Color = Color::rgba(0.0, 0.0, 0.0, 0.6);
+
+/// The main menu, the loading screen and the pause menu, each shown in its [`GameState`].
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_main_menu.in_schedule(OnEnter(GameState::MainMenu)))
+            .add_system(despawn_menu.in_schedule(OnExit(GameState::MainMenu)))
+            .add_system(spawn_loading_screen.in_schedule(OnEnter(GameState::Loading)))
+            .add_system(despawn_menu.in_schedule(OnExit(GameState::Loading)))
+            .add_system(spawn_pause_menu.in_schedule(OnEnter(GameState::Paused)))
+            .add_system(despawn_menu.in_schedule(OnExit(GameState::Paused)))
+            .add_system(press_menu_buttons);
+    }
+}
+
+/// Root of whichever menu is shown, despawned when leaving its state.
+#[derive(Component)]
+struct MenuRoot;
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+enum MenuButton {
+    Play,
+    Resume,
+    /// Shows the key bindings in place of the pause menu.
+    Settings,
+    /// Goes back from the key bindings to the pause menu.
+    Back,
+    Quit,
+}
+
+impl MenuButton {
+    fn label(self) -> &'static str {
+        match self {
+            MenuButton::Play => "Play",
+            MenuButton::Resume => "Resume",
+            MenuButton::Settings => "Settings",
+            MenuButton::Back => "Back",
+            MenuButton::Quit => "Quit",
+        }
+    }
+}
+
+fn text_style(asset_server: &AssetServer, font_size: f32) -> TextStyle {
+    return TextStyle {
+        font: asset_server.load("font/TiltWarp-Regular.ttf"),
+        font_size,
+        color: Color::WHITE,
+    };
+}
+
+/// Spawns a full screen menu with a title and whatever `content` adds below it.
+fn spawn_menu(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    title: &str,
+    background: Color,
+    content: impl FnOnce(&mut ChildBuilder),
+) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::all(Val::Percent(100.0)),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    gap: Size::all(Val::Px(10.0)),
+                    ..default()
+                },
+                background_color: background.into(),
+                // above the inventory screen and everything else
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            MenuRoot,
+            Name::new(format!("Menu {}", title)),
+        ))
+        .with_children(|menu| {
+            menu.spawn(
+                TextBundle::from_section(title, text_style(asset_server, 60.0)).with_style(Style {
+                    margin: UiRect::bottom(Val::Px(20.0)),
+                    ..default()
+                }),
+            );
+
+            content(menu);
+        });
+}
+
+fn spawn_button(menu: &mut ChildBuilder, asset_server: &AssetServer, button: MenuButton) {
+    menu.spawn((
+        ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(250.0), Val::Px(55.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: BUTTON_COLOR.into(),
+            ..default()
+        },
+        button,
+    ))
+    .with_children(|button_node| {
+        button_node.spawn(TextBundle::from_section(
+            button.label(),
+            text_style(asset_server, 30.0),
+        ));
+    });
+}
+
+fn spawn_main_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    spawn_menu(
+        &mut commands,
+        &asset_server,
+        "Bevy Game",
+        OPAQUE_BACKGROUND,
+        |menu| {
+            spawn_button(menu, &asset_server, MenuButton::Play);
+            spawn_button(menu, &asset_server, MenuButton::Quit);
+        },
+    );
+}
+
+fn spawn_loading_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    spawn_menu(
+        &mut commands,
+        &asset_server,
+        "Generating world",
+        OPAQUE_BACKGROUND,
+        |_| {},
+    );
+}
+
+fn spawn_pause_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    pause_menu(&mut commands, &asset_server);
+}
+
+fn pause_menu(commands: &mut Commands, asset_server: &AssetServer) {
+    spawn_menu(
+        commands,
+        asset_server,
+        "Paused",
+        PAUSED_BACKGROUND,
+        |menu| {
+            spawn_button(menu, asset_server, MenuButton::Resume);
+            spawn_button(menu, asset_server, MenuButton::Settings);
+            spawn_button(menu, asset_server, MenuButton::Quit);
+        },
+    );
+}
+
+/// Lists which keys and buttons every action is bound to, see `assets/input.ron`.
+fn settings_menu(commands: &mut Commands, asset_server: &AssetServer, input_map: &InputMap) {
+    let mut lines: Vec<String> = input_map
+        .bindings
+        .iter()
+        .map(|(action, bindings)| format!("{:?}: {:?}", action, bindings))
+        .collect();
+    lines.sort();
+
+    spawn_menu(
+        commands,
+        asset_server,
+        "Settings",
+        PAUSED_BACKGROUND,
+        |menu| {
+            menu.spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ..default()
+            })
+            .with_children(|list| {
+                for line in lines {
+                    list.spawn(TextBundle::from_section(
+                        line,
+                        text_style(asset_server, 18.0),
+                    ));
+                }
+            });
+
+            spawn_button(menu, asset_server, MenuButton::Back);
+        },
+    );
+}
+
+fn despawn_menu(mut commands: Commands, menus: Query<Entity, With<MenuRoot>>) {
+    for entity in menus.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn press_menu_buttons(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    input_map: Res<InputMap>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut app_exit: EventWriter<AppExit>,
+    mut buttons: Query<(&Interaction, &MenuButton, &mut BackgroundColor), Changed<Interaction>>,
+    menus: Query<Entity, With<MenuRoot>>,
+) {
+    for (interaction, button, mut background_color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {}
+            Interaction::Hovered => {
+                background_color.0 = BUTTON_HOVERED_COLOR;
+                continue;
+            }
+            Interaction::None => {
+                background_color.0 = BUTTON_COLOR;
+                continue;
+            }
+        }
+
+        match button {
+            MenuButton::Play => next_state.set(GameState::Loading),
+            MenuButton::Resume => next_state.set(GameState::InGame),
+            MenuButton::Settings | MenuButton::Back => {
+                for entity in menus.iter() {
+                    commands.entity(entity).despawn_recursive();
+                }
+
+                if *button == MenuButton::Settings {
+                    settings_menu(&mut commands, &asset_server, &input_map);
+                } else {
+                    pause_menu(&mut commands, &asset_server);
+                }
+
+                // the menu is gone, and with it the rest of the buttons
+                return;
+            }
+            MenuButton::Quit => app_exit.send(AppExit),
+        }
+    }
+}
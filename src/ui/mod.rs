@@ -3,19 +3,30 @@ use bevy::{
     prelude::*,
 };
 
-use self::inventory::*;
+use self::{inventory::*, inventory_screen::InventoryScreenPlugin, menu::MenuPlugin};
+use crate::event::{BlockPlacementRejected, PlacementRejection};
+
+/// Seconds the reason a block could not be placed stays on screen.
+const PLACEMENT_MESSAGE_DURATION: f32 = 1.5;
 
 pub mod inventory;
+pub mod inventory_screen;
+pub mod menu;
 
 pub struct UserInterfacePlugin;
 
 impl Plugin for UserInterfacePlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugin(FrameTimeDiagnosticsPlugin::default())
+        app.add_plugin(FrameTimeDiagnosticsPlugin)
             .add_plugin(InventorySystemPlugin)
+            .add_plugin(InventoryScreenPlugin)
+            .add_plugin(MenuPlugin)
             .add_startup_system(initialize_fps_counter_system)
             .add_startup_system(initialize_crosshair)
-            .add_system(ui_update_system);
+            .add_startup_system(initialize_placement_message)
+            .add_system(ui_update_system)
+            .add_system(show_placement_message)
+            .add_system(hide_placement_message.after(show_placement_message));
     }
 }
 
@@ -83,3 +94,73 @@ fn ui_update_system(diagnostics: Res<Diagnostics>, mut query: Query<&mut Text, W
         }
     }
 }
+
+/// Text above the hotbar telling why the last block could not be placed, hidden after a while.
+#[derive(Component)]
+struct PlacementMessage {
+    timer: Timer,
+}
+
+fn initialize_placement_message(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(80.0),
+                    ..default()
+                },
+                size: Size::width(Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|container| {
+            container.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("font/TiltWarp-Regular.ttf"),
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                    },
+                )
+                .with_text_alignment(TextAlignment::Center),
+                PlacementMessage {
+                    timer: Timer::from_seconds(PLACEMENT_MESSAGE_DURATION, TimerMode::Once),
+                },
+            ));
+        });
+}
+
+fn placement_message(reason: PlacementRejection) -> &'static str {
+    match reason {
+        PlacementRejection::Occupied => "There is already a block there",
+        PlacementRejection::IntersectsPlayer => "You are in the way",
+        PlacementRejection::IntersectsEntity(_) => "Something is in the way",
+        PlacementRejection::NothingSelected => "Nothing selected to place",
+    }
+}
+
+fn show_placement_message(
+    mut placement_rejected: EventReader<BlockPlacementRejected>,
+    mut query: Query<(&mut Text, &mut PlacementMessage)>,
+) {
+    let Some(rejected) = placement_rejected.iter().last() else {
+        return;
+    };
+
+    for (mut text, mut message) in &mut query {
+        text.sections[0].value = placement_message(rejected.reason).to_string();
+        message.timer.reset();
+    }
+}
+
+fn hide_placement_message(time: Res<Time>, mut query: Query<(&mut Text, &mut PlacementMessage)>) {
+    for (mut text, mut message) in &mut query {
+        if message.timer.tick(time.delta()).just_finished() {
+            text.sections[0].value.clear();
+        }
+    }
+}
@@ -1,3 +1,34 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::de::DeserializeOwned;
+
+/// Why a file could not be loaded by [`load_ron`], along with the file.
+#[derive(Debug)]
+pub enum LoadError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, ron::error::SpannedError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Read(path, error) => write!(f, "Could not read {:?}: {}", path, error),
+            LoadError::Parse(path, error) => write!(f, "Invalid {:?}: {}", path, error),
+        }
+    }
+}
+
+/// Reads a RON file into `T`, used for the definitions and settings in the asset folder.
+pub fn load_ron<T: DeserializeOwned>(path: &Path) -> Result<T, LoadError> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|error| LoadError::Read(path.to_path_buf(), error))?;
+
+    return ron::from_str(&source).map_err(|error| LoadError::Parse(path.to_path_buf(), error));
+}
+
 // use bevy::prelude::*;
 
 // pub fn get_target_with_normals(